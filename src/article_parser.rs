@@ -1,281 +1,549 @@
-use parse_wiki_text::{Configuration, Node};
+use std::path::Path;
+
+use parse_wiki_text::{Configuration, DefinitionListItemType, Node, Positioned, TableCellType};
+use serde::{Deserialize, Serialize};
+
+mod date_templates;
+mod dictionary_segmenter;
+mod html;
+mod markdown;
+mod page_kind;
+mod section_filter;
+mod segmentation;
+mod template_rules;
+mod templates;
+
+pub use page_kind::{page_kind, PageKind};
+pub use section_filter::SectionLimit;
+pub use segmentation::{Segmentation, WordBoundaries};
+pub use templates::{TemplateCall, TemplateHandler, TemplateRegistry};
 
 #[derive(Debug)]
 #[derive(Clone)]
 #[derive(PartialEq)]
+#[derive(Serialize, Deserialize)]
 pub enum Token {
-    Word(String),
-    NonWord(String),
+    Word(String, Markup, Span),
+    NonWord(String, Markup, Span),
+
+    // From <br> in the wiki text
+    LineBreak(Span),
+}
+
+// Byte positions in the wiki text that a token came from, which covers
+// the whole template or link the text is part of. Empty for text that
+// is not from the wiki text, like the title.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(Default)]
+#[derive(PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
 }
 
 #[derive(Debug)]
 #[derive(Clone)]
+#[derive(Default)]
 #[derive(PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct Markup {
+    pub bold: bool,
+    pub italic: bool,
+
+    // Title of the wiki page the token links to
+    pub link: Option<String>,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Serialize, Deserialize)]
 pub enum Section {
     Heading(usize, Vec<Token>),
     Paragraph(Vec<Token>),
     UnorderedList(Vec<Vec<Section>>),
     OrderedList(Vec<Vec<Section>>),
+
+    // Label and value of each row
+    Infobox(Vec<(Vec<Token>, Vec<Token>)>),
+
+    // Caption and rows of cells
+    Table(Option<Vec<Token>>, Vec<Vec<TableCell>>),
+
+    // Caption of an image, which itself is not shown
+    Figure(Vec<Token>),
+
+    // Terms with their details; details may come without a term
+    DefinitionList(Vec<(Vec<Token>, Vec<Vec<Section>>)>),
+
+    Quote(Vec<Section>),
+
+    // Keeps its line breaks and spacing
+    Preformatted(Vec<Token>),
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct TableCell {
+    pub header: bool,
+    pub tokens: Vec<Token>,
 }
 
 #[derive(Debug)]
 #[derive(Clone)]
 #[derive(PartialEq)]
+#[derive(Serialize, Deserialize)]
 pub struct WikiArticle {
     pub title: Vec<Token>,
     pub content: Vec<Section>,
 }
 
-impl Token {
-    pub fn get_str(&self) -> &str {
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct ParseWarning {
+    // Byte positions in the wiki text
+    pub start: usize,
+    pub end: usize,
+
+    pub message: String,
+    pub snippet: String,
+}
+
+// What the content of an article is written in
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum SourceFormat {
+    // As editors write it, with templates that are only partly understood here
+    #[default]
+    Wikitext,
+
+    // Parsoid HTML, as Wikipedia renders it, with all templates expanded
+    Html,
+
+    // Custom texts from files, titled by their first heading or file name
+    Markdown,
+    PlainText,
+}
+
+impl SourceFormat {
+    pub const ALL: [SourceFormat; 4] = [
+        SourceFormat::Wikitext,
+        SourceFormat::Html,
+        SourceFormat::Markdown,
+        SourceFormat::PlainText,
+    ];
+
+    // The formats Wikipedia serves articles in
+    pub const DOWNLOADABLE: [SourceFormat; 2] = [SourceFormat::Wikitext, SourceFormat::Html];
+
+    pub fn name(&self) -> &'static str {
         match self {
-            Token::Word(w) => w.as_str(),
-            Token::NonWord(nw) => nw.as_str(),
+            SourceFormat::Html => "html",
+            SourceFormat::Markdown => "markdown",
+            SourceFormat::PlainText => "plain text",
+            SourceFormat::Wikitext => "wikitext",
         }
     }
 
-    pub fn char_count(&self) -> usize {
-        let string = self.get_str();
-        string.chars().count()
+    pub fn from_name(name: &str) -> Option<SourceFormat> {
+        SourceFormat::ALL.into_iter().find(|format| format.name() == name)
+    }
+
+    // Files of unknown kinds are taken for wiki text
+    pub fn for_file(path: &Path) -> SourceFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("htm" | "html") => SourceFormat::Html,
+            Some("markdown" | "md") => SourceFormat::Markdown,
+            Some("txt") => SourceFormat::PlainText,
+            _ => SourceFormat::Wikitext,
+        }
     }
 }
 
-fn chop_into_tokens(input: &str) -> Vec<Token> {
+#[derive(Default)]
+pub struct ParserConfig {
+    pub templates: TemplateRegistry,
 
-    let mut result: Vec<Token> = Vec::new();
-    let mut current: Vec<char> = Vec::new();
+    // Gather the content of <ref> tags into a section of notes at the
+    // end, instead of dropping it
+    pub collect_notes: bool,
 
-    let chars: Vec<char> = input.chars().collect();
-    let mut i = 0;
+    // Keep sections like "References" and "See also"
+    pub keep_appendices: bool,
 
-    // Check for a possible NonWord at the beginning.
-    while i < chars.len() && !chars[i].is_alphanumeric() {
-        current.push(chars[i]);
-        i += 1;
-    }
+    pub section_limit: SectionLimit,
 
-    if current.len() > 0 {
-        result.push(Token::NonWord(current.into_iter().collect()));
-    }
+    pub segmentation: Segmentation,
+}
 
-    if i == chars.len() { return result; }
+// Markup of tokens that cannot have any
+static NO_MARKUP: Markup = Markup { bold: false, italic: false, link: None };
 
-    loop {
-        // Now, we alternative between Words and NonWords until the end.
+impl Span {
+    fn of(element: &impl Positioned) -> Span {
+        Span { start: element.start(), end: element.end() }
+    }
 
-        let mut current: Vec<char> = Vec::new();
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
 
-        while i < chars.len() && chars[i].is_alphanumeric() {
-            current.push(chars[i]);
-            i += 1;
+    // Covers both spans, ignoring empty ones
+    pub fn union(self, other: Span) -> Span {
+        if self.is_empty() {
+            other
+        } else if other.is_empty() {
+            self
+        } else {
+            Span { start: self.start.min(other.start), end: self.end.max(other.end) }
         }
-        assert!(current.len() > 0);
-        result.push(Token::Word(current.into_iter().collect()));
-
-        if i == chars.len() { break; }
+    }
+}
 
-        let mut current: Vec<char> = Vec::new();
+impl Token {
+    pub fn get_str(&self) -> &str {
+        match self {
+            Token::Word(w, ..) => w.as_str(),
+            Token::NonWord(nw, ..) => nw.as_str(),
+            Token::LineBreak(_) => "\n",
+        }
+    }
 
-        while i < chars.len() && !chars[i].is_alphanumeric() {
-            current.push(chars[i]);
-            i += 1;
+    pub fn markup(&self) -> &Markup {
+        match self {
+            Token::Word(_, markup, _) | Token::NonWord(_, markup, _) => markup,
+            Token::LineBreak(_) => &NO_MARKUP,
         }
-        assert!(current.len() > 0);
-        result.push(Token::NonWord(current.into_iter().collect()));
+    }
 
-        if i == chars.len() { break; }
+    pub fn span(&self) -> Span {
+        match self {
+            Token::Word(.., span) | Token::NonWord(.., span) | Token::LineBreak(span) => *span,
+        }
     }
 
-    result
+    pub fn char_count(&self) -> usize {
+        let string = self.get_str();
+        string.chars().count()
+    }
 }
 
-fn get_parameter_by_name(parameters: &Vec<parse_wiki_text::Parameter>, name: &str) -> Option<String> {
-    for param in parameters.iter() {
-        if let Some(name_nodes) = &param.name {
-            let n = get_inline_text(&name_nodes);
-            if n == name {
-                return Some(get_inline_text(&param.value));
+impl Section {
+    // The wiki text the tokens of the section came from. Markup around
+    // them, like the equal signs of headings, may be left out.
+    pub fn span(&self) -> Span {
+        self.tokens()
+            .iter()
+            .fold(Span::default(), |span, token| span.union(token.span()))
+    }
+
+    // All tokens of the section and its subsections, in reading order
+    pub fn tokens(&self) -> Vec<&Token> {
+        match self {
+            Section::Heading(_, tokens) | Section::Paragraph(tokens) |
+            Section::Figure(tokens) | Section::Preformatted(tokens) => {
+                tokens.iter().collect()
+            }
+
+            Section::DefinitionList(entries) => {
+                entries
+                    .iter()
+                    .flat_map(|(term, details)| {
+                        term.iter().chain(details.iter().flatten().flat_map(|section| section.tokens()))
+                    })
+                    .collect()
+            }
+
+            Section::Quote(sections) => {
+                sections
+                    .iter()
+                    .flat_map(|section| section.tokens())
+                    .collect()
+            }
+
+            Section::UnorderedList(items) | Section::OrderedList(items) => {
+                items
+                    .iter()
+                    .flatten()
+                    .flat_map(|section| section.tokens())
+                    .collect()
+            }
+
+            Section::Infobox(rows) => {
+                rows
+                    .iter()
+                    .flat_map(|(label, value)| label.iter().chain(value.iter()))
+                    .collect()
+            }
+
+            Section::Table(caption, rows) => {
+                caption
+                    .iter()
+                    .flatten()
+                    .chain(rows.iter().flatten().flat_map(|cell| cell.tokens.iter()))
+                    .collect()
             }
         }
     }
-    None
 }
 
-fn get_template_text(template: &Node) -> String {
-    let Node::Template { name, parameters, .. } = template else { panic!("Argument must be template") };
-
-    let name = get_inline_text(name);
+// A character of text, with its markup and where it came from
+type StyledChar<'m> = (char, &'m Markup, Span);
 
-    if name.to_lowercase().starts_with("lang-") {
-        let native  = parameters
-            .get(0)
-            .map(|param| { get_inline_text(&param.value) })
-            .unwrap_or(String::from(""));
+// Paragraph text as it is collected, with the markup and source of each part
+#[derive(Default)]
+struct StyledText {
+    runs: Vec<(String, Markup, Span)>,
 
-        let translit = get_parameter_by_name(parameters, "translit");
+    // Markup and source of text pushed next
+    markup: Markup,
+    span: Span,
+}
 
-        if let Some(tl) = translit {
-            format!("{} ({})", native, tl)
-        } else {
-            native
+impl StyledText {
+    fn append(&mut self, text: &str) {
+        match self.runs.last_mut() {
+            Some((run, markup, span)) if *markup == self.markup && *span == self.span => run.push_str(text),
+            _ => self.runs.push((String::from(text), self.markup.clone(), self.span)),
         }
-    } else {
-        match name.to_lowercase().as_str() {
-            "as of" => {
-                let year = parameters
-                    .get(0)
-                    .map(|param| { get_inline_text(&param.value) });
-
-                match year {
-                    None => String::from(name),
-                    Some(y) => format!("{} {}", name, y),
-                }
-            }
+    }
 
-            "abbr" => {
-                let short_form =
-                    parameters
-                    .get(0)
-                    .map(|param| { get_inline_text(&param.value) });
+    // Single newlines in the wiki text do not break lines
+    fn push_str(&mut self, text: &str) {
+        self.append(&text.replace('\n', " "));
+    }
 
-                let long_form =
-                    parameters
-                    .get(1)
-                    .map(|param| { get_inline_text(&param.value) });
+    fn push_line_break(&mut self) {
+        self.append("\n");
+    }
 
-                match (long_form, short_form) {
-                    (None, None) => String::from(""),
+    fn push(&mut self, character: char) {
+        self.push_str(character.encode_utf8(&mut [0; 4]));
+    }
 
-                    (Some(long), None) => String::from(long),
+    fn is_blank(&self) -> bool {
+        self.runs.iter().all(|(run, ..)| run.trim().is_empty())
+    }
 
-                    (None, Some(short)) => String::from(short),
+    // Keeps the markup, as the text after an image or infobox in the
+    // middle of a paragraph still has it
+    fn clear(&mut self) {
+        self.runs.clear();
+    }
 
-                    (Some(long), Some(short)) => format!("{} ({})", long, short)
-                }
-            }
+    // Tokens of the text without surrounding whitespace
+    fn tokens(&self, config: &ParserConfig) -> Vec<Token> {
+        let chars: Vec<StyledChar> = self.runs
+            .iter()
+            .flat_map(|(run, markup, span)| run.chars().map(move |c| (c, markup, *span)))
+            .collect();
 
-            "annotated link" | "italics correction" => {
-                parameters
-                    .get(0)
-                    .map(|param| { get_inline_text(&param.value) })
-                    .unwrap_or(String::from(""))
-            }
+        let start = chars.iter().position(|(c, ..)| !c.is_whitespace()).unwrap_or(chars.len());
+        let end = chars.iter().rposition(|(c, ..)| !c.is_whitespace()).map_or(start, |i| i + 1);
+
+        chop_styled_into_tokens(config, &chars[start..end])
+    }
+}
 
+fn chop_into_tokens(config: &ParserConfig, input: &str, span: Span) -> Vec<Token> {
+    let markup = Markup::default();
+    let chars: Vec<StyledChar> = input.chars().map(|c| (c, &markup, span)).collect();
 
-            "blockquote" => {
-                let quote =
-                    parameters
-                    .get(0)
-                    .map(|param| { get_inline_text(&param.value) });
+    chop_styled_into_tokens(config, &chars)
+}
 
-                let source =
-                    parameters
-                    .get(1)
-                    .map(|param| { get_inline_text(&param.value) });
+// Line breaks become tokens of their own
+fn chop_styled_into_tokens(config: &ParserConfig, chars: &[StyledChar]) -> Vec<Token> {
+    let mut result = Vec::new();
+    let mut line_start = 0;
 
-                let mut result = String::new();
+    for (i, (c, _, span)) in chars.iter().enumerate() {
+        if *c == '\n' {
+            result.extend(config.segmentation.tokens(&chars[line_start..i]));
+            result.push(Token::LineBreak(*span));
+            line_start = i + 1;
+        }
+    }
 
-                if let Some(q) = quote {
-                    result.push_str("“");
-                    result.push_str(&q);
-                    result.push_str("”");
+    result.extend(config.segmentation.tokens(&chars[line_start..]));
+    result
+}
 
-                    if let Some(s) = source {
-                        result.push_str(" – ");
-                        result.push_str(&s);
-                    }
-                }
+fn get_template_text(config: &ParserConfig, name: &Vec<Node>, parameters: &Vec<parse_wiki_text::Parameter>) -> String {
+    let call = TemplateCall {
+        name: get_inline_text(config, name).trim().to_string(),
+        parameters,
+        config,
+    };
 
-                result
-            }
+    match config.templates.lookup(&call.name) {
+        Some(handler) => handler.expand(&call),
+        None => String::from(""),
+    }
+}
 
-            "circa" => {
-                let value = parameters
-                    .get(0)
-                    .map(|param| { get_inline_text(&param.value) })
-                    .unwrap_or(String::from(""));
-		format!("circa {}", value)
-            }
+// Joins lines and removes surplus spaces, for text that is shown in boxes
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().intersperse(" ").collect()
+}
 
-            "cite encyclopedia" => {
-                get_parameter_by_name(parameters, &"encyclopedia")
-                    .unwrap_or(String::from(""))
-            }
+fn is_infobox(config: &ParserConfig, name: &Vec<Node>) -> bool {
+    get_inline_text(config, name)
+        .trim()
+        .to_lowercase()
+        .starts_with("infobox")
+}
 
-            "cite book" | "cite journal" | "cite web" | "cite news" | "cite report" | "cite periodical" => {
-                get_parameter_by_name(parameters, &"title")
-                    .unwrap_or(String::from(""))
-            }
+// Infobox parameters that only control the layout, or hold file names
+fn is_layout_parameter(name: &str) -> bool {
+    const PREFIXES: [&str; 10] = [
+        "image", "logo", "signature", "map", "pushpin",
+        "module", "embed", "upright", "coordinates", "coords",
+    ];
+
+    PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+        || name == "alt"
+        || name.ends_with(" alt")
+        || name.ends_with("size")
+}
 
-            "cvt" | "convert" => {
-                let number =
-                    parameters
-                    .get(0)
-                    .map(|param| { get_inline_text(&param.value) })
-                    .unwrap_or(String::from("???"));
+fn get_infobox_rows(config: &ParserConfig, parameters: &Vec<parse_wiki_text::Parameter>, notes: &mut Vec<Vec<Token>>) -> Vec<(Vec<Token>, Vec<Token>)> {
+    let mut rows = Vec::new();
 
-                let unit =
-                    parameters
-                    .get(1)
-                    .map(|param| { get_inline_text(&param.value) });
+    for param in parameters {
+        // Unnamed parameters are not shown by infoboxes
+        let Some(name_nodes) = &param.name else { continue };
 
-                if let Some(ref u) = unit {
-                    if u == "to" {
-                        // We have a range of values, given as {{convert|min|to|max|unit|...}}
+        let label = get_inline_text(config, name_nodes).trim().replace('_', " ");
+        if label.is_empty() || is_layout_parameter(&label.to_lowercase()) {
+            continue;
+        }
 
-                        let maximum =
-                            parameters
-                            .get(2)
-                            .map(|param| { get_inline_text(&param.value) })
-                            .unwrap_or(String::from("???"));
+        let value = collapse_whitespace(&get_inline_text(config, &param.value));
+        if value.is_empty() {
+            continue;
+        }
+        collect_notes(config, &param.value, notes);
 
-                        let unit =
-                            parameters
-                            .get(3)
-                            .map(|param| { get_inline_text(&param.value) });
+        // Parameter names are usually lowercase, like "birth_place"
+        let mut chars = label.chars();
+        let label: String = chars.next().into_iter().flat_map(char::to_uppercase).chain(chars).collect();
 
-                        return match unit {
-                            None => format!("{} to {}", number, maximum),
-                            Some(u) => format!("{} to {} {}", number, maximum, u),
-                        };
+        let span = Span::of(param);
+        rows.push((chop_into_tokens(config, &label, span), chop_into_tokens(config, &value, span)));
+    }
+
+    rows
+}
+
+fn get_table(config: &ParserConfig, captions: &[parse_wiki_text::TableCaption], rows: &[parse_wiki_text::TableRow], notes: &mut Vec<Vec<Token>>) -> Option<Section> {
+    let caption: String = captions
+        .iter()
+        .map(|caption| {
+            collect_notes(config, &caption.content, notes);
+            collapse_whitespace(&get_inline_text(config, &caption.content))
+        })
+        .intersperse(String::from(" "))
+        .collect();
+
+    let rows: Vec<Vec<TableCell>> = rows
+        .iter()
+        .map(|row| {
+            row.cells
+                .iter()
+                .map(|cell| {
+                    collect_notes(config, &cell.content, notes);
+
+                    TableCell {
+                        header: matches!(cell.type_, TableCellType::Heading),
+                        tokens: chop_into_tokens(config, &collapse_whitespace(&get_inline_text(config, &cell.content)), Span::of(cell)),
                     }
-                }
+                })
+                .collect::<Vec<TableCell>>()
+        })
+        .filter(|cells| !cells.is_empty())
+        .collect();
+
+    if rows.is_empty() {
+        return None;
+    }
 
-                return match unit {
-                    None => format!("{}", number),
-                    Some(u) => format!("{} {}", number, u),
-                };
-            }
+    let caption_span = captions.iter().map(Span::of).fold(Span::default(), Span::union);
+    let caption = if caption.trim().is_empty() { None } else { Some(chop_into_tokens(config, caption.trim(), caption_span)) };
 
-            "endash" => {
-                String::from("–")
-            }
+    Some(Section::Table(caption, rows))
+}
 
-            "lang" | "wikt-lang" => {
-                parameters
-                    .get(1)
-                    .map(|param| { get_inline_text(&param.value) })
-                    .unwrap_or(String::from(""))
-            }
+fn is_image_option(option: &str) -> bool {
+    const KEYWORDS: [&str; 20] = [
+        "thumb", "thumbnail", "frame", "framed", "frameless", "border",
+        "left", "right", "center", "centre", "none", "upright",
+        "baseline", "middle", "sub", "super", "top", "text-top", "bottom", "text-bottom",
+    ];
+    const PARAMETERS: [&str; 8] = ["upright", "alt", "link", "page", "class", "lang", "thumb", "thumbnail"];
 
-            "rms" | "ss" => {
-                let ship_name =
-                    parameters
-                    .get(0)
-                    .map(|param| { get_inline_text(&param.value) })
-                    .unwrap_or(String::from(""));
+    let option = option.trim().to_lowercase();
 
-                format!("{} {}", name, ship_name)
-            }
+    // Sizes look like "200px", "x100px" or "200x100px"
+    let is_size = option
+        .strip_suffix("px")
+        .is_some_and(|size| !size.is_empty() && size.chars().all(|c| c.is_ascii_digit() || c == 'x'));
 
-            _ => {
-                String::from("")
+    let is_parameter = option
+        .split_once('=')
+        .is_some_and(|(key, _)| PARAMETERS.contains(&key.trim()));
+
+    KEYWORDS.contains(&option.as_str()) || is_size || is_parameter
+}
+
+// The caption is the last part of "thumb|200px|Caption" that is not an option
+fn get_caption(config: &ParserConfig, text: &str, span: Span) -> Option<Vec<Token>> {
+    text.split('|')
+        .map(collapse_whitespace)
+        .rfind(|part| !part.is_empty() && !is_image_option(part))
+        .map(|caption| chop_into_tokens(config, &caption, span))
+}
+
+// Each line of a gallery is a file name, followed by options and a caption
+fn get_gallery_captions(config: &ParserConfig, nodes: &Vec<Node>, span: Span, notes: &mut Vec<Vec<Token>>) -> Vec<Section> {
+    collect_notes(config, nodes, notes);
+
+    get_inline_text(config, nodes)
+        .lines()
+        .filter_map(|line| line.split_once('|'))
+        .filter_map(|(_file, text)| get_caption(config, text, span))
+        .map(Section::Figure)
+        .collect()
+}
+
+// The parser leaves tags it does not know in the text. Of those,
+// <templatestyles> is common and never shows up on the page.
+fn strip_templatestyles(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("<templatestyles") {
+        result.push_str(&rest[..start]);
+
+        match rest[start..].find('>') {
+            Some(end) => rest = &rest[start + end + 1..],
+            None => {
+                rest = &rest[start..];
+                break;
             }
         }
     }
+
+    result.push_str(rest);
+    result
 }
 
-fn get_inline_text(nodes: &Vec<Node>) -> String {
+fn get_inline_text(config: &ParserConfig, nodes: &Vec<Node>) -> String {
     let mut result: String = String::new();
 
     for node in nodes {
@@ -284,18 +552,27 @@ fn get_inline_text(nodes: &Vec<Node>) -> String {
                 result.push(*character);
             }
 
+            // Inline text has no line breaks
+            Node::EndTag { name, .. } | Node::StartTag { name, .. } if name == "br" => {
+                result.push(' ');
+            }
+
             Node::Link { text: nodes, ..} => {
-                let link_text = get_inline_text(nodes);
+                let link_text = get_inline_text(config, nodes);
                 result.push_str(&link_text);
             }
 
-            Node::Template { .. } => {
-                let template_text = get_template_text(node);
+            Node::Tag { name, nodes, .. } if name == "nowiki" => {
+                result.push_str(&get_inline_text(config, nodes));
+            }
+
+            Node::Template { name, parameters, .. } => {
+                let template_text = get_template_text(config, name, parameters);
                 result.push_str(&template_text);
             }
 
             Node::Text { value, .. } => {
-                result.push_str(value);
+                result.push_str(&strip_templatestyles(value));
             }
 
             _ => {
@@ -306,35 +583,138 @@ fn get_inline_text(nodes: &Vec<Node>) -> String {
     result
 }
 
-fn get_sections(nodes: &Vec<Node>) -> Vec<Section> {
+fn push_note(config: &ParserConfig, node: &Node, nodes: &Vec<Node>, notes: &mut Vec<Vec<Token>>) {
+    let note = collapse_whitespace(&get_inline_text(config, nodes));
 
-    let mut result: Vec<Section> = Vec::new();
-    let mut current_para = String::new();
+    if config.collect_notes && !note.is_empty() {
+        notes.push(chop_into_tokens(config, &note, Span::of(node)));
+    }
+}
 
+// Inline text leaves out <ref> tags, so the notes in headings, captions,
+// table cells and infoboxes are gathered from their nodes separately
+fn collect_notes(config: &ParserConfig, nodes: &Vec<Node>, notes: &mut Vec<Vec<Token>>) {
     for node in nodes {
+        match node {
+            Node::Image { text: nodes, .. } | Node::Link { text: nodes, .. } => {
+                collect_notes(config, nodes, notes);
+            }
+
+            Node::Tag { name, nodes, .. } if name == "ref" => {
+                push_note(config, node, nodes, notes);
+            }
+
+            Node::Template { parameters, .. } => {
+                for parameter in parameters {
+                    collect_notes(config, &parameter.value, notes);
+                }
+            }
+
+            _ => { }
+        }
+    }
+}
+
+// Links to sections of the same page lead nowhere new
+fn get_link_page(target: &str) -> Option<String> {
+    let page = target.split('#').next().unwrap_or("").trim();
+
+    if page.is_empty() {
+        None
+    } else {
+        Some(String::from(page))
+    }
+}
+
+fn get_definition_list(config: &ParserConfig, items: &Vec<parse_wiki_text::DefinitionListItem>, notes: &mut Vec<Vec<Token>>) -> Section {
+    let mut entries: Vec<(Vec<Token>, Vec<Vec<Section>>)> = Vec::new();
+
+    for item in items {
+        match item.type_ {
+            DefinitionListItemType::Term => {
+                collect_notes(config, &item.nodes, notes);
+                let term = collapse_whitespace(&get_inline_text(config, &item.nodes));
+                entries.push((chop_into_tokens(config, &term, Span::of(item)), Vec::new()));
+            }
+
+            DefinitionListItemType::Details => {
+                // Lines starting with ":" are often just indented, without a term
+                if entries.is_empty() {
+                    entries.push((Vec::new(), Vec::new()));
+                }
+
+                let details = get_sections(config, &item.nodes, notes);
+                entries.last_mut().unwrap().1.push(details);
+            }
+        }
+    }
+
+    Section::DefinitionList(entries)
+}
+
+fn end_paragraph(config: &ParserConfig, result: &mut Vec<Section>, current_para: &mut StyledText) {
+    if !current_para.is_blank() {
+        result.push(Section::Paragraph(current_para.tokens(config)));
+    }
+    current_para.clear();
+}
+
+fn get_sections<'a, 'b>(config: &ParserConfig, nodes: impl IntoIterator<Item = &'b Node<'a>>, notes: &mut Vec<Vec<Token>>) -> Vec<Section>
+where 'a: 'b {
+
+    let mut result: Vec<Section> = Vec::new();
+    let mut current_para = StyledText::default();
+
+    let mut nodes = nodes.into_iter().peekable();
+
+    while let Some(node) = nodes.next() {
+        current_para.span = Span::of(node);
+
         // These nodes end the previous paragraph
         match node {
+            Node::DefinitionList { .. } |
             Node::Heading { .. } |
             Node::HorizontalDivider { .. } |
             Node::OrderedList { .. } |
             Node::ParagraphBreak { .. } |
+            Node::Preformatted { .. } |
+            Node::Table { .. } |
             Node::UnorderedList { .. } => {
-                if !current_para.trim().is_empty() {
-                    result.push(Section::Paragraph(chop_into_tokens(current_para.trim())));
-                    current_para = String::new();
-                }
+                end_paragraph(config, &mut result, &mut current_para);
+
+                // Bold and italic text never goes on past the end of a paragraph
+                current_para.markup = Markup::default();
             }
 
             _ => { }
         }
 
         match node {
+            Node::Bold { .. } => {
+                current_para.markup.bold = !current_para.markup.bold;
+            }
+
+            Node::BoldItalic { .. } => {
+                current_para.markup.bold = !current_para.markup.bold;
+                current_para.markup.italic = !current_para.markup.italic;
+            }
+
             Node::CharacterEntity { character, .. } => {
                 current_para.push(*character);
             }
 
+            Node::DefinitionList { items, .. } => {
+                result.push(get_definition_list(config, items, notes));
+            }
+
+            // Other inline tags such as <span> or <small> are dropped,
+            // but the text in between is kept
+            Node::EndTag { name, .. } | Node::StartTag { name, .. } if name == "br" => {
+                current_para.push_line_break();
+            }
+
             Node::ExternalLink { nodes, ..} => {
-                let link_text = get_inline_text(nodes);
+                let link_text = get_inline_text(config, nodes);
                 let without_url: String = link_text
                     .split_whitespace()
                     .skip(1)
@@ -344,39 +724,126 @@ fn get_sections(nodes: &Vec<Node>) -> Vec<Section> {
             }
 
             Node::Heading { level, nodes, .. } => {
-                let heading_text = get_inline_text(nodes);
-                result.push(Section::Heading(*level as usize, chop_into_tokens(&heading_text)));
+                collect_notes(config, nodes, notes);
+                let heading_text = get_inline_text(config, nodes);
+                result.push(Section::Heading(*level as usize, chop_into_tokens(config, &heading_text, Span::of(node))));
             }
 
-            Node::Link { text: nodes, ..} => {
-                let link_text = get_inline_text(nodes);
+            Node::Image { text, .. } => {
+                collect_notes(config, text, notes);
+
+                if let Some(caption) = get_caption(config, &get_inline_text(config, text), Span::of(node)) {
+                    end_paragraph(config, &mut result, &mut current_para);
+
+                    result.push(Section::Figure(caption));
+                }
+            }
+
+            Node::Italic { .. } => {
+                current_para.markup.italic = !current_para.markup.italic;
+            }
+
+            Node::Link { target, text: nodes, ..} => {
+                let link_text = get_inline_text(config, nodes);
+
+                current_para.markup.link = get_link_page(target);
                 current_para.push_str(&link_text);
+                current_para.markup.link = None;
             }
 
             Node::OrderedList { items, .. } => {
                 let mut sections: Vec<Vec<Section>> = Vec::new();
 
                 for item in items {
-                    sections.push(get_sections(&item.nodes));
+                    sections.push(get_sections(config, &item.nodes, notes));
                 }
 
                 result.push(Section::OrderedList(sections));
             }
 
-            Node::Template { .. } => {
-                let template_text = get_template_text(node);
+            Node::Preformatted { nodes, .. } => {
+                result.push(Section::Preformatted(chop_into_tokens(config, &get_inline_text(config, nodes), Span::of(node))));
+            }
+
+            // The quoted text follows up to the matching end tag, or up to
+            // the next heading if the end tag is missing
+            Node::StartTag { name, .. } if name == "blockquote" => {
+                end_paragraph(config, &mut result, &mut current_para);
+
+                let mut quoted: Vec<&Node> = Vec::new();
+                let mut depth = 0;
+
+                while let Some(node) = nodes.next_if(|node| !matches!(node, Node::Heading { .. })) {
+                    match node {
+                        Node::StartTag { name, .. } if name == "blockquote" => {
+                            depth += 1;
+                        }
+
+                        Node::EndTag { name, .. } if name == "blockquote" => {
+                            if depth == 0 {
+                                break;
+                            }
+                            depth -= 1;
+                        }
+
+                        _ => { }
+                    }
+
+                    quoted.push(node);
+                }
+
+                result.push(Section::Quote(get_sections(config, quoted, notes)));
+            }
+
+            Node::Table { captions, rows, .. } => {
+                if let Some(table) = get_table(config, captions, rows, notes) {
+                    result.push(table);
+                }
+            }
+
+            Node::Tag { name, nodes, .. } if name == "gallery" => {
+                end_paragraph(config, &mut result, &mut current_para);
+
+                result.extend(get_gallery_captions(config, nodes, Span::of(node), notes));
+            }
+
+            Node::Tag { name, nodes, .. } if name == "nowiki" => {
+                current_para.push_str(&get_inline_text(config, nodes));
+            }
+
+            Node::Tag { name, nodes, .. } if name == "pre" => {
+                end_paragraph(config, &mut result, &mut current_para);
+
+                result.push(Section::Preformatted(chop_into_tokens(config, &get_inline_text(config, nodes), Span::of(node))));
+            }
+
+            Node::Tag { name, nodes, .. } if name == "ref" => {
+                push_note(config, node, nodes, notes);
+            }
+
+            Node::Template { name, parameters, .. } if is_infobox(config, name) => {
+                end_paragraph(config, &mut result, &mut current_para);
+
+                let rows = get_infobox_rows(config, parameters, notes);
+                if !rows.is_empty() {
+                    result.push(Section::Infobox(rows));
+                }
+            }
+
+            Node::Template { name, parameters, .. } => {
+                let template_text = get_template_text(config, name, parameters);
                 current_para.push_str(&template_text);
             }
 
             Node::Text { value, .. } => {
-                current_para.push_str(value);
+                current_para.push_str(&strip_templatestyles(value));
             }
 
             Node::UnorderedList { items, .. } => {
                 let mut sections: Vec<Vec<Section>> = Vec::new();
 
                 for item in items {
-                    sections.push(get_sections(&item.nodes));
+                    sections.push(get_sections(config, &item.nodes, notes));
                 }
 
                 result.push(Section::UnorderedList(sections));
@@ -387,30 +854,264 @@ fn get_sections(nodes: &Vec<Node>) -> Vec<Section> {
         }
     }
 
-    if !current_para.trim().is_empty() {
-        result.push(Section::Paragraph(chop_into_tokens(current_para.trim())));
-    }
+    end_paragraph(config, &mut result, &mut current_para);
 
     result
 }
 
-pub fn parse(title: &str, content: &str) -> WikiArticle {
+// The page a redirect page points to, if the wiki text is one
+pub fn redirect_target(content: &str) -> Option<String> {
+    let parsed = Configuration::default().parse(content);
+
+    match parsed.nodes.first() {
+        Some(Node::Redirect { target, .. }) => get_link_page(target),
+        _ => None,
+    }
+}
+
+// Longest snippet of wiki text kept with a warning
+const MAX_SNIPPET_CHARS: usize = 80;
+
+pub fn parse(config: &ParserConfig, language: &str, title: &str, content: &str) -> (WikiArticle, Vec<ParseWarning>) {
 
     let parsed = Configuration::default().parse(content);
 
-    if !parsed.warnings.is_empty() {
-        println!("Warnings while parsing wiki text:");
-        for warn in &parsed.warnings {
-            println!("{} - {}: {}", warn.start, warn.end, warn.message.message());
-        }
+    let warnings = parsed.warnings
+        .iter()
+        .map(|warn| {
+            ParseWarning {
+                start: warn.start,
+                end: warn.end,
+                message: String::from(warn.message.message()),
+                snippet: content
+                    .get(warn.start..warn.end)
+                    .unwrap_or("")
+                    .chars()
+                    .take(MAX_SNIPPET_CHARS)
+                    .collect(),
+            }
+        })
+        .collect();
+
+    let mut notes = Vec::new();
+    let nodes = section_filter::filter_nodes(config, language, &parsed.nodes);
+    let content = get_sections(config, nodes, &mut notes);
+
+    (finish_article(config, language, title, content, notes), warnings)
+}
+
+// The HTML parser has no warnings to give, the HTML is always valid
+pub fn parse_html(config: &ParserConfig, language: &str, title: &str, content: &str) -> WikiArticle {
+    let (content, notes) = html::parse(config, language, content);
+
+    finish_article(config, language, title, content, notes)
+}
+
+// A heading at the start of the text replaces the given title
+pub fn parse_markdown(config: &ParserConfig, language: &str, title: &str, content: &str) -> WikiArticle {
+    let (heading, content) = markdown::parse(config, language, content);
+
+    finish_article(config, language, heading.as_deref().unwrap_or(title), content, Vec::new())
+}
+
+pub fn parse_plain_text(config: &ParserConfig, language: &str, title: &str, content: &str) -> WikiArticle {
+    let content = markdown::parse_plain_text(config, content);
+
+    finish_article(config, language, title, content, Vec::new())
+}
+
+pub fn parse_source(config: &ParserConfig, format: SourceFormat, language: &str, title: &str, content: &str) -> (WikiArticle, Vec<ParseWarning>) {
+    match format {
+        SourceFormat::Html => (parse_html(config, language, title, content), Vec::new()),
+        SourceFormat::Markdown => (parse_markdown(config, language, title, content), Vec::new()),
+        SourceFormat::PlainText => (parse_plain_text(config, language, title, content), Vec::new()),
+        SourceFormat::Wikitext => parse(config, language, title, content),
     }
+}
 
-    let content = get_sections(&parsed.nodes);
+// The title of the page, as Parsoid HTML has it
+pub fn html_title(content: &str) -> Option<String> {
+    html::title(content)
+}
+
+fn finish_article(config: &ParserConfig, language: &str, title: &str, mut content: Vec<Section>, notes: Vec<Vec<Token>>) -> WikiArticle {
+    if !notes.is_empty() {
+        let heading = section_filter::notes_heading(language);
+        content.push(Section::Heading(2, chop_into_tokens(config, heading, Span::default())));
+        content.push(Section::OrderedList(
+            notes.into_iter().map(|note| vec![Section::Paragraph(note)]).collect()
+        ));
+    }
 
-    let title_tokens = chop_into_tokens(title);
+    let mut title_tokens = chop_into_tokens(config, title, Span::default());
+
+    // Languages written without spaces need a dictionary for their words
+    if let Some(segmenter) = dictionary_segmenter::DictionarySegmenter::for_language(language) {
+        title_tokens = segmenter.split_tokens(title_tokens);
+        segmenter.split_sections(&mut content);
+    }
 
     WikiArticle{
         title: title_tokens,
-        content: content,
+        content,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // The text of tokens as it would be shown with all words revealed
+    pub fn text<'a>(tokens: impl IntoIterator<Item = &'a Token>) -> String {
+        tokens.into_iter().map(Token::get_str).collect()
+    }
+
+    pub fn section_texts(sections: &[Section]) -> Vec<String> {
+        sections.iter().map(|section| text(section.tokens())).collect()
+    }
+
+    // All text of a piece of wiki text, for checking what templates expand to
+    pub fn expand(config: &ParserConfig, wikitext: &str) -> String {
+        let article = parse(config, "en", "Test", wikitext).0;
+        section_texts(&article.content).concat()
+    }
+
+    fn parse_text(content: &str) -> Vec<Section> {
+        parse(&ParserConfig::default(), "en", "Test", content).0.content
+    }
+
+    fn bold_words(sections: &[Section]) -> Vec<&str> {
+        sections
+            .iter()
+            .flat_map(Section::tokens)
+            .filter(|token| matches!(token, Token::Word(..)) && token.markup().bold)
+            .map(Token::get_str)
+            .collect()
+    }
+
+    fn notes_of(language: &str, content: &str) -> Vec<String> {
+        let config = ParserConfig { collect_notes: true, ..Default::default() };
+        let article = parse(&config, language, "Test", content).0;
+
+        let Some(Section::OrderedList(notes)) = article.content.last() else { return Vec::new() };
+        notes.iter().map(|note| section_texts(note).concat()).collect()
+    }
+
+    #[test]
+    fn notes_from_everywhere() {
+        let content = "{{Infobox animal\n| name = Cat<ref>Value note</ref>\n}}\n\
+            Text.<ref>Paragraph note</ref>\n\
+            [[File:Cat.jpg|thumb|A cat<ref>Caption note</ref>]]\n\
+            == Heading<ref>Heading note</ref> ==\n\
+            {|\n|+ Caption<ref>Table caption note</ref>\n|-\n| Cell<ref>Cell note</ref>\n|}\n";
+
+        assert_eq!(notes_of("en", content), vec![
+            "Value note", "Paragraph note", "Caption note", "Heading note", "Table caption note", "Cell note",
+        ]);
+    }
+
+    #[test]
+    fn notes_heading_in_the_article_language() {
+        let config = ParserConfig { collect_notes: true, ..Default::default() };
+
+        let article = parse(&config, "de", "Test", "Text.<ref>Note</ref>").0;
+        assert_eq!(section_texts(&article.content), vec!["Text.", "Anmerkungen", "Note"]);
+
+        let article = parse(&config, "xx", "Test", "Text.<ref>Note</ref>").0;
+        assert_eq!(section_texts(&article.content), vec!["Text.", "Notes", "Note"]);
+    }
+
+    #[test]
+    fn spans_point_into_the_wiki_text() {
+        let content = "The '''cat''' is a [[Felidae|felid]].\n== Name ==\nIn French {{lang|fr|chat}}.";
+        let article = parse(&ParserConfig::default(), "en", "Cat", content).0;
+
+        let sources: Vec<(&str, &str)> = article.content
+            .iter()
+            .flat_map(Section::tokens)
+            .filter(|token| matches!(token, Token::Word(..)))
+            .map(|token| (token.get_str(), &content[token.span().start..token.span().end]))
+            .collect();
+
+        // Words of plain text point at the whole text around them, and
+        // words from links and templates at the whole link or template
+        assert_eq!(sources, vec![
+            ("The", "The "), ("cat", "cat"), ("is", " is a "), ("a", " is a "), ("felid", "[[Felidae|felid]]"),
+            ("Name", "== Name =="),
+            ("In", "In French "), ("French", "In French "), ("chat", "{{lang|fr|chat}}"),
+        ]);
+
+        assert_eq!(&content[article.content[1].span().start..article.content[1].span().end], "== Name ==");
+    }
+
+    #[test]
+    fn section_limits() {
+        let content = "Lead.\n== One ==\nFirst.\n=== Sub ===\nStill first.\n== Two ==\nSecond.\n== Three ==\nThird.";
+        let sections_with = |section_limit| {
+            let config = ParserConfig { section_limit, ..Default::default() };
+            section_texts(&parse(&config, "en", "Test", content).0.content)
+        };
+
+        assert_eq!(sections_with(SectionLimit::All).len(), 9);
+        assert_eq!(sections_with(SectionLimit::LeadOnly), vec!["Lead."]);
+        assert_eq!(sections_with(SectionLimit::First(1)), vec!["Lead.", "One", "First.", "Sub", "Still first."]);
+        assert_eq!(sections_with(SectionLimit::First(2)).last().unwrap(), "Second.");
+    }
+
+    #[test]
+    fn appendices_left_out() {
+        let content = "Text.\n== History ==\nMore.\n== See also ==\n* [[Dog]]\n=== Related ===\nLinks.\n== Legacy ==\nLast.\n== External links ==\n* [https://example.org Site]";
+
+        let article = parse(&ParserConfig::default(), "en", "Test", content).0;
+        assert_eq!(section_texts(&article.content), vec!["Text.", "History", "More.", "Legacy", "Last."]);
+
+        let config = ParserConfig { keep_appendices: true, ..Default::default() };
+        let article = parse(&config, "en", "Test", content).0;
+        assert_eq!(section_texts(&article.content).len(), 11);
+
+        // Headings are matched in the language of the article
+        let content = "Text.\n== Weblinks ==\n* [https://example.org Site]\n== See also ==\nKept.";
+        let article = parse(&ParserConfig::default(), "de", "Test", content).0;
+        assert_eq!(section_texts(&article.content), vec!["Text.", "See also", "Kept."]);
+    }
+
+    #[test]
+    fn nested_quotes() {
+        let content = parse_text("Before <blockquote>Outer <blockquote>Inner</blockquote> more outer</blockquote> after");
+
+        assert_eq!(content.len(), 3);
+        assert_eq!(section_texts(&content[..1]), vec!["Before"]);
+        assert_eq!(section_texts(&content[2..]), vec!["after"]);
+
+        let Section::Quote(outer) = &content[1] else { panic!("no quote in {:?}", content) };
+        assert_eq!(section_texts(outer), vec!["Outer", "Inner", "more outer"]);
+        assert!(matches!(&outer[1], Section::Quote(_)));
+    }
+
+    #[test]
+    fn unclosed_quotes_end_at_the_next_heading() {
+        let content = parse_text("<blockquote>Quoted\n\n== Heading ==\nText");
+
+        assert_eq!(section_texts(&content), vec!["Quoted", "Heading", "Text"]);
+        assert!(matches!(&content[0], Section::Quote(_)));
+        assert!(matches!(&content[1], Section::Heading(..)));
+    }
+
+    #[test]
+    fn markup_goes_on_after_images() {
+        let content = parse_text("'''Bold [[File:Cat.jpg|thumb|A caption]] still bold''' and ''italic'' words");
+
+        assert_eq!(section_texts(&content), vec!["Bold", "A caption", "still bold and italic words"]);
+        assert_eq!(bold_words(&content), vec!["Bold", "still", "bold"]);
+
+        let italic: Vec<&str> = content[2].tokens().into_iter().filter(|token| token.markup().italic).map(Token::get_str).collect();
+        assert_eq!(italic, vec!["italic"]);
+    }
+
+    #[test]
+    fn markup_ends_with_the_paragraph() {
+        let content = parse_text("'''Unclosed bold\n\nNext paragraph");
+
+        assert_eq!(bold_words(&content), vec!["Unclosed", "bold"]);
     }
 }
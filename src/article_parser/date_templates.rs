@@ -0,0 +1,292 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::templates::{TemplateCall, TemplateRegistry};
+
+// Templates for dates and ages, as found in biographies:
+//
+//   {{birth date|1879|3|14}}                  March 14, 1879
+//   {{birth date|1879|3|14|df=y}}             14 March 1879
+//   {{death date and age|1955|4|18|1879|3|14}}  April 18, 1955 (aged 76)
+//   {{birth year and age|1950}}               1950 (age 75–76)
+//
+// Ages of living people are computed from today's date.
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+#[derive(Clone, Copy)]
+struct Date {
+    year: i64,
+    month: Option<u32>,
+    day: Option<u32>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DateOrder {
+    MonthFirst,
+    DayFirst,
+}
+
+fn is_yes(value: Option<String>) -> bool {
+    matches!(value.as_deref().map(str::trim).map(str::to_lowercase).as_deref(), Some("y" | "yes"))
+}
+
+// Month first is the default, also chosen with mf=y
+fn date_order(call: &TemplateCall) -> DateOrder {
+    if is_yes(call.named("df")) && !is_yes(call.named("mf")) {
+        DateOrder::DayFirst
+    } else {
+        DateOrder::MonthFirst
+    }
+}
+
+fn parse_month(text: &str) -> Option<u32> {
+    let text = text.trim();
+
+    if let Ok(month) = text.parse::<u32>() {
+        return (1..=12).contains(&month).then_some(month);
+    }
+
+    // Also accept month names, and abbreviations like "Mar"
+    let lower = text.to_lowercase();
+    if lower.len() < 3 {
+        return None;
+    }
+
+    MONTHS
+        .iter()
+        .position(|name| name.to_lowercase().starts_with(&lower))
+        .map(|index| index as u32 + 1)
+}
+
+// Reads year, month and day from consecutive numbered parameters
+fn date_parameters(call: &TemplateCall, first: usize) -> Option<Date> {
+    let year = call.numbered(first)?.trim().parse().ok()?;
+    let month = call.numbered(first + 1).and_then(|m| parse_month(&m));
+    let day = month
+        .and(call.numbered(first + 2))
+        .and_then(|d| d.trim().parse().ok())
+        .filter(|d| (1..=31).contains(d));
+
+    Some(Date { year, month, day })
+}
+
+fn format_date(date: &Date, order: DateOrder) -> String {
+    match (date.month, date.day) {
+        (Some(month), Some(day)) => {
+            let month = MONTHS[month as usize - 1];
+
+            match order {
+                DateOrder::MonthFirst => format!("{} {}, {}", month, day, date.year),
+                DateOrder::DayFirst => format!("{} {} {}", day, month, date.year),
+            }
+        }
+
+        (Some(month), None) => format!("{} {}", MONTHS[month as usize - 1], date.year),
+
+        _ => format!("{}", date.year),
+    }
+}
+
+// Converts days since 1970-01-01 to a date in the proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> Date {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    Date { year, month: Some(month as u32), day: Some(day as u32) }
+}
+
+fn today() -> Date {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0);
+
+    civil_from_days(seconds.div_euclid(86400))
+}
+
+// The youngest and oldest someone born at `birth` can be at `at`, as
+// the dates may be incomplete
+fn age(birth: &Date, at: &Date) -> (i64, i64) {
+    let years = at.year - birth.year;
+
+    match (birth.month, at.month) {
+        (Some(birth_month), Some(at_month)) if birth_month != at_month => {
+            if at_month < birth_month { (years - 1, years - 1) } else { (years, years) }
+        }
+
+        (Some(_), Some(_)) => {
+            match (birth.day, at.day) {
+                (Some(birth_day), Some(at_day)) => {
+                    if at_day < birth_day { (years - 1, years - 1) } else { (years, years) }
+                }
+                _ => (years - 1, years),
+            }
+        }
+
+        _ => (years - 1, years),
+    }
+}
+
+fn format_age(word: &str, (youngest, oldest): (i64, i64)) -> String {
+    if youngest == oldest {
+        format!("({} {})", word, youngest)
+    } else {
+        format!("({} {}–{})", word, youngest, oldest)
+    }
+}
+
+fn date(call: &TemplateCall) -> String {
+    date_parameters(call, 1)
+        .map(|date| format_date(&date, date_order(call)))
+        .unwrap_or_default()
+}
+
+fn birth_date_and_age(call: &TemplateCall) -> String {
+    let Some(birth) = date_parameters(call, 1) else { return String::new() };
+
+    format!("{} {}", format_date(&birth, date_order(call)), format_age("age", age(&birth, &today())))
+}
+
+fn death_date_and_age(call: &TemplateCall) -> String {
+    let Some(death) = date_parameters(call, 1) else { return String::new() };
+    let death_text = format_date(&death, date_order(call));
+
+    match date_parameters(call, 4) {
+        Some(birth) => format!("{} {}", death_text, format_age("aged", age(&birth, &death))),
+        None => death_text,
+    }
+}
+
+// {{birth year and age|year|month}}
+fn birth_year_and_age(call: &TemplateCall) -> String {
+    let Some(year) = call.numbered(1).and_then(|y| y.trim().parse().ok()) else { return String::new() };
+    let month = call.numbered(2).and_then(|m| parse_month(&m));
+    let birth = Date { year, month, day: None };
+
+    format!("{} {}", format_date(&birth, date_order(call)), format_age("age", age(&birth, &today())))
+}
+
+// {{death year and age|death year|birth year|death month}}
+fn death_year_and_age(call: &TemplateCall) -> String {
+    let Some(year) = call.numbered(1).and_then(|y| y.trim().parse().ok()) else { return String::new() };
+    let month = call.numbered(3).and_then(|m| parse_month(&m));
+    let death = Date { year, month, day: None };
+    let death_text = format_date(&death, date_order(call));
+
+    match call.numbered(2).and_then(|y| y.trim().parse().ok()) {
+        Some(birth_year) => {
+            let birth = Date { year: birth_year, month: None, day: None };
+            format!("{} {}", death_text, format_age("aged", age(&birth, &death)))
+        }
+        None => death_text,
+    }
+}
+
+fn start_date_and_age(call: &TemplateCall) -> String {
+    let Some(start) = date_parameters(call, 1) else { return String::new() };
+
+    let (_, years) = age(&start, &today());
+    let ago = match years {
+        0 => String::from("(less than a year ago)"),
+        1 => String::from("(1 year ago)"),
+        _ => format!("({} years ago)", years),
+    };
+
+    format!("{} {}", format_date(&start, date_order(call)), ago)
+}
+
+pub fn register(registry: &mut TemplateRegistry) {
+    registry.register_all(&["birth date", "death date", "start date", "end date", "film date"], date);
+    registry.register("birth date and age", birth_date_and_age);
+    registry.register("death date and age", death_date_and_age);
+    registry.register("birth year and age", birth_year_and_age);
+    registry.register("death year and age", death_year_and_age);
+    registry.register("start date and age", start_date_and_age);
+
+    registry.alias("dob", "birth date");
+    registry.alias("bda", "birth date and age");
+    registry.alias("dda", "death date and age");
+    registry.alias("birth-date and age", "birth date and age");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::article_parser::tests;
+    use crate::article_parser::ParserConfig;
+
+    fn expand(wikitext: &str) -> String {
+        tests::expand(&ParserConfig::default(), wikitext)
+    }
+
+    fn day(year: i64, month: u32, day: u32) -> Date {
+        Date { year, month: Some(month), day: Some(day) }
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(expand("{{birth date|1879|3|14}}"), "March 14, 1879");
+        assert_eq!(expand("{{birth date|1879|3|14|df=y}}"), "14 March 1879");
+        assert_eq!(expand("{{dob|1879|Mar|14|mf=yes}}"), "March 14, 1879");
+        assert_eq!(expand("{{start date|1969|7}}"), "July 1969");
+        assert_eq!(expand("{{end date|1969}}"), "1969");
+        assert_eq!(expand("Born {{birth date|unknown}}."), "Born .");
+    }
+
+    #[test]
+    fn ages_at_death() {
+        assert_eq!(expand("{{death date and age|1955|4|18|1879|3|14}}"), "April 18, 1955 (aged 76)");
+        assert_eq!(expand("{{dda|1955|3|13|1879|3|14|df=y}}"), "13 March 1955 (aged 75)");
+        assert_eq!(expand("{{death year and age|1955|1879}}"), "1955 (aged 75–76)");
+    }
+
+    #[test]
+    fn ages_of_the_living() {
+        let text = expand("{{birth date and age|1950|1|1}}");
+        assert!(text.starts_with("January 1, 1950 (age "), "{}", text);
+
+        let text = expand("{{birth year and age|1950}}");
+        assert!(text.starts_with("1950 (age ") && text.contains('–'), "{}", text);
+    }
+
+    #[test]
+    fn months() {
+        assert_eq!(parse_month("3"), Some(3));
+        assert_eq!(parse_month("13"), None);
+        assert_eq!(parse_month("September"), Some(9));
+        assert_eq!(parse_month("sep"), Some(9));
+        assert_eq!(parse_month("ju"), None);
+    }
+
+    #[test]
+    fn ages() {
+        assert_eq!(age(&day(1879, 3, 14), &day(1955, 4, 18)), (76, 76));
+        assert_eq!(age(&day(1879, 3, 14), &day(1955, 3, 13)), (75, 75));
+        assert_eq!(age(&day(1879, 3, 14), &day(1955, 3, 14)), (76, 76));
+
+        let year_only = Date { year: 1879, month: None, day: None };
+        assert_eq!(age(&year_only, &day(1955, 3, 14)), (75, 76));
+    }
+
+    #[test]
+    fn days_since_1970() {
+        let date = civil_from_days(0);
+        assert_eq!((date.year, date.month, date.day), (1970, Some(1), Some(1)));
+
+        let date = civil_from_days(19_782);
+        assert_eq!((date.year, date.month, date.day), (2024, Some(2), Some(29)));
+
+        let date = civil_from_days(-1);
+        assert_eq!((date.year, date.month, date.day), (1969, Some(12), Some(31)));
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::HashSet;
+use std::mem;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{Markup, Section, Span, Token};
+
+// Chinese, Japanese and Thai are written without spaces between words,
+// so the words are looked up in a dictionary. Han characters and Thai
+// graphemes (characters with their combining vowels and tone marks) that
+// are not part of a known word become words of their own.
+
+const BUILTIN_CHINESE: &str = include_str!("../assets/dictionaries/zh.txt");
+const BUILTIN_JAPANESE: &str = include_str!("../assets/dictionaries/ja.txt");
+const BUILTIN_THAI: &str = include_str!("../assets/dictionaries/th.txt");
+
+#[derive(Clone, Copy, PartialEq)]
+enum Script {
+    Chinese,
+    Japanese,
+    Thai,
+}
+
+fn is_han(c: char) -> bool {
+    matches!(c,
+        '\u{3005}' | '\u{3007}' |
+        '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' |
+        '\u{F900}'..='\u{FAFF}' | '\u{20000}'..='\u{2FFFF}')
+}
+
+fn is_hiragana(c: char) -> bool {
+    matches!(c, '\u{3041}'..='\u{309F}')
+}
+
+fn is_katakana(c: char) -> bool {
+    matches!(c, '\u{30A0}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}')
+}
+
+fn is_thai(c: char) -> bool {
+    matches!(c, '\u{0E01}'..='\u{0E5B}')
+}
+
+// Characters of all the scripts above
+pub fn is_unspaced(c: char) -> bool {
+    is_han(c) || is_hiragana(c) || is_katakana(c) || is_thai(c)
+}
+
+impl Script {
+    fn for_language(language: &str) -> Option<Script> {
+        match language {
+            "ja" => Some(Script::Japanese),
+            "th" => Some(Script::Thai),
+            "zh" | "zh-classical" | "zh-yue" | "gan" | "wuu" => Some(Script::Chinese),
+            _ => None,
+        }
+    }
+
+    fn contains(&self, c: char) -> bool {
+        match self {
+            Script::Chinese => is_han(c),
+            Script::Japanese => is_han(c) || is_hiragana(c) || is_katakana(c),
+            Script::Thai => is_thai(c),
+        }
+    }
+
+    fn builtin_dictionary(&self) -> &'static str {
+        match self {
+            Script::Chinese => BUILTIN_CHINESE,
+            Script::Japanese => BUILTIN_JAPANESE,
+            Script::Thai => BUILTIN_THAI,
+        }
+    }
+}
+
+pub struct DictionarySegmenter {
+    script: Script,
+    words: HashSet<String>,
+
+    // In characters
+    longest: usize,
+}
+
+// Dictionaries are only read once, the first time an article needs them
+static CHINESE: OnceLock<DictionarySegmenter> = OnceLock::new();
+static JAPANESE: OnceLock<DictionarySegmenter> = OnceLock::new();
+static THAI: OnceLock<DictionarySegmenter> = OnceLock::new();
+
+impl DictionarySegmenter {
+    fn new(script: Script) -> DictionarySegmenter {
+        // One word per line, like the word frequency list
+        let words: HashSet<String> = script
+            .builtin_dictionary()
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(String::from)
+            .collect();
+
+        let longest = words.iter().map(|word| word.chars().count()).max().unwrap_or(0);
+
+        DictionarySegmenter { script, words, longest }
+    }
+
+    // None for languages that separate words with spaces
+    pub fn for_language(language: &str) -> Option<&'static DictionarySegmenter> {
+        let script = Script::for_language(language)?;
+
+        let segmenter = match script {
+            Script::Chinese => &CHINESE,
+            Script::Japanese => &JAPANESE,
+            Script::Thai => &THAI,
+        };
+
+        Some(segmenter.get_or_init(|| DictionarySegmenter::new(script)))
+    }
+
+    // Number of graphemes of the longest known word at the start of
+    // `graphemes`, so Thai vowels and tone marks stay with their consonant
+    fn longest_match(&self, graphemes: &[&str]) -> Option<usize> {
+        let mut candidate = String::new();
+        let mut result = None;
+
+        for (count, grapheme) in graphemes.iter().enumerate() {
+            candidate.push_str(grapheme);
+
+            if candidate.chars().count() > self.longest {
+                break;
+            }
+            if self.words.contains(&candidate) {
+                result = Some(count + 1);
+            }
+        }
+
+        result
+    }
+
+    // Words of a run of text in the script, as ranges of graphemes
+    fn segment(&self, graphemes: &[&str]) -> Vec<Range<usize>> {
+        let mut result = Vec::new();
+
+        // Start of text that is not in the dictionary, which becomes a
+        // single word once a known word follows
+        let mut unknown: Option<usize> = None;
+
+        let mut i = 0;
+        while i < graphemes.len() {
+            let first = graphemes[i].chars().next().unwrap_or_default();
+
+            let length = match self.longest_match(&graphemes[i..]) {
+                Some(length) => Some(length),
+                None if is_han(first) => Some(1),
+
+                // Thai has no characters to tell where words end, so
+                // unknown text is cut into graphemes rather than left as
+                // one long blank
+                None if self.script == Script::Thai => Some(1),
+
+                // Katakana is mostly used for loanwords, which are written
+                // together
+                None if is_katakana(first) => {
+                    let run = graphemes[i..]
+                        .iter()
+                        .take_while(|g| g.chars().next().is_some_and(is_katakana))
+                        .count();
+                    Some(run)
+                }
+
+                None => None,
+            };
+
+            match length {
+                Some(length) => {
+                    if let Some(start) = unknown.take() {
+                        result.push(start..i);
+                    }
+                    result.push(i..i + length);
+                    i += length;
+                }
+
+                None => {
+                    unknown.get_or_insert(i);
+                    i += 1;
+                }
+            }
+        }
+
+        if let Some(start) = unknown {
+            result.push(start..graphemes.len());
+        }
+
+        result
+    }
+
+    fn push_words(&self, result: &mut Vec<Token>, run: &mut Vec<(char, Markup, Span)>) {
+        if run.is_empty() {
+            return;
+        }
+
+        let text: String = run.iter().map(|(c, ..)| *c).collect();
+        let graphemes: Vec<&str> = text.graphemes(true).collect();
+
+        let mut start = 0;
+        for range in self.segment(&graphemes) {
+            let word = graphemes[range].concat();
+            let end = start + word.chars().count();
+
+            let markup = run[start].1.clone();
+            let span = run[start..end].iter().fold(Span::default(), |span, (.., s)| span.union(*s));
+
+            start = end;
+
+            // Like digits and punctuation of Thai
+            if word.chars().any(char::is_alphabetic) {
+                result.push(Token::Word(word, markup, span));
+            } else {
+                result.push(Token::NonWord(word, markup, span));
+            }
+        }
+
+        run.clear();
+    }
+
+    // Splits the parts of the tokens that are written in the script into
+    // words. Tokens come from the other segmentation first, which may
+    // have cut them anywhere.
+    pub fn split_tokens(&self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut result = Vec::new();
+        let mut run: Vec<(char, Markup, Span)> = Vec::new();
+
+        for token in tokens {
+            let (text, markup, span, is_word) = match token {
+                Token::Word(text, markup, span) => (text, markup, span, true),
+                Token::NonWord(text, markup, span) => (text, markup, span, false),
+
+                Token::LineBreak(span) => {
+                    self.push_words(&mut result, &mut run);
+                    result.push(Token::LineBreak(span));
+                    continue;
+                }
+            };
+
+            let mut other = String::new();
+
+            for c in text.chars() {
+                if self.script.contains(c) {
+                    push_token(&mut result, &mut other, &markup, span, is_word);
+                    run.push((c, markup.clone(), span));
+                } else {
+                    self.push_words(&mut result, &mut run);
+                    other.push(c);
+                }
+            }
+
+            push_token(&mut result, &mut other, &markup, span, is_word);
+        }
+
+        self.push_words(&mut result, &mut run);
+        result
+    }
+
+    pub fn split_sections(&self, sections: &mut [Section]) {
+        for section in sections {
+            self.split_section(section);
+        }
+    }
+
+    fn split_in_place(&self, tokens: &mut Vec<Token>) {
+        *tokens = self.split_tokens(mem::take(tokens));
+    }
+
+    fn split_section(&self, section: &mut Section) {
+        match section {
+            Section::Heading(_, tokens) | Section::Paragraph(tokens) |
+            Section::Figure(tokens) | Section::Preformatted(tokens) => {
+                self.split_in_place(tokens);
+            }
+
+            Section::DefinitionList(entries) => {
+                for (term, details) in entries {
+                    self.split_in_place(term);
+
+                    for detail in details {
+                        self.split_sections(detail);
+                    }
+                }
+            }
+
+            Section::Quote(sections) => {
+                self.split_sections(sections);
+            }
+
+            Section::UnorderedList(items) | Section::OrderedList(items) => {
+                for item in items {
+                    self.split_sections(item);
+                }
+            }
+
+            Section::Infobox(rows) => {
+                for (label, value) in rows {
+                    self.split_in_place(label);
+                    self.split_in_place(value);
+                }
+            }
+
+            Section::Table(caption, rows) => {
+                if let Some(caption) = caption {
+                    self.split_in_place(caption);
+                }
+
+                for cell in rows.iter_mut().flatten() {
+                    self.split_in_place(&mut cell.tokens);
+                }
+            }
+        }
+    }
+}
+
+// Text of a token outside the script keeps the kind of its token
+fn push_token(result: &mut Vec<Token>, text: &mut String, markup: &Markup, span: Span, is_word: bool) {
+    if text.is_empty() {
+        return;
+    }
+
+    let text = mem::take(text);
+    result.push(if is_word { Token::Word(text, markup.clone(), span) } else { Token::NonWord(text, markup.clone(), span) });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::article_parser::chop_into_tokens;
+    use crate::article_parser::ParserConfig;
+
+    fn split(language: &str, text: &str) -> Vec<(bool, String)> {
+        let segmenter = DictionarySegmenter::for_language(language).unwrap();
+        let tokens = chop_into_tokens(&ParserConfig::default(), text, Span::default());
+
+        segmenter
+            .split_tokens(tokens)
+            .into_iter()
+            .map(|token| (matches!(token, Token::Word(..)), String::from(token.get_str())))
+            .collect()
+    }
+
+    fn words(tokens: &[(bool, String)]) -> Vec<&str> {
+        tokens.iter().filter(|(word, _)| *word).map(|(_, text)| text.as_str()).collect()
+    }
+
+    #[test]
+    fn dictionaries_are_read_once() {
+        let first = DictionarySegmenter::for_language("zh").unwrap();
+        let second = DictionarySegmenter::for_language("zh").unwrap();
+        assert!(std::ptr::eq(first, second));
+
+        assert!(DictionarySegmenter::for_language("en").is_none());
+    }
+
+    #[test]
+    fn chinese_words_and_single_characters() {
+        let tokens = split("zh", "中国的首都是北京。");
+
+        assert_eq!(words(&tokens), vec!["中国", "的", "首都", "是", "北京"]);
+        assert_eq!(tokens.last(), Some(&(false, String::from("。"))));
+    }
+
+    #[test]
+    fn japanese_katakana_stays_together() {
+        let tokens = split("ja", "コンピュータの歴史");
+
+        assert_eq!(words(&tokens)[0], "コンピュータ");
+    }
+
+    #[test]
+    fn thai_digits_are_not_words() {
+        let tokens = split("th", "ประเทศไทย ๒๕๖๗");
+
+        assert_eq!(words(&tokens).concat(), "ประเทศไทย");
+        assert!(tokens.iter().any(|(word, text)| !word && text == "๒"));
+    }
+
+    #[test]
+    fn unknown_thai_is_cut_into_graphemes() {
+        // Not in the dictionary, so no word is longer than a character
+        // with its vowels and tone marks
+        let tokens = split("th", "ฬฮฬ่");
+
+        assert_eq!(words(&tokens), vec!["ฬ", "ฮ", "ฬ่"]);
+    }
+}
@@ -0,0 +1,659 @@
+use std::collections::HashMap;
+
+use kuchiki::traits::TendrilSink;
+use kuchiki::NodeRef;
+
+use super::{chop_into_tokens, collapse_whitespace, end_paragraph, get_link_page, section_filter};
+use super::{ParserConfig, Section, Span, StyledText, TableCell, Token};
+
+// Parsoid HTML, as served by the REST API of Wikipedia. Templates are
+// already expanded in it, so it shows the article as readers see it,
+// but it does not know where the text was in the wiki text.
+
+// Elements that never show text of the article
+const HIDDEN_ELEMENTS: [&str; 6] = ["head", "img", "link", "meta", "script", "style"];
+
+// Boxes around the article, which templates of the wiki text would not
+// show either, and the list of references, which is read on its own
+const HIDDEN_CLASSES: [&str; 13] = [
+    "ambox", "hatnote", "metadata", "mw-empty-elt", "mw-references-wrap", "navbox",
+    "noprint", "portalbox", "references", "shortdescription", "sidebar", "sistersitebox",
+    "vertical-navbox",
+];
+
+fn element_name(node: &NodeRef) -> Option<&str> {
+    node.as_element().map(|element| &*element.name.local)
+}
+
+fn attribute(node: &NodeRef, name: &str) -> Option<String> {
+    node.as_element()?.attributes.borrow().get(name).map(String::from)
+}
+
+fn has_class(node: &NodeRef, class: &str) -> bool {
+    attribute(node, "class").is_some_and(|classes| classes.split_whitespace().any(|c| c == class))
+}
+
+fn is_hidden(node: &NodeRef) -> bool {
+    match element_name(node) {
+        Some(name) => HIDDEN_ELEMENTS.contains(&name) || HIDDEN_CLASSES.iter().any(|class| has_class(node, class)),
+        None => node.as_comment().is_some(),
+    }
+}
+
+// Links to references look like <sup class="mw-ref reference">
+fn is_reference(node: &NodeRef) -> bool {
+    element_name(node) == Some("sup") && has_class(node, "mw-ref")
+}
+
+fn heading_level(node: &NodeRef) -> Option<u8> {
+    match element_name(node)? {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    }
+}
+
+// Parsoid leaves URLs of pages percent encoded
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut result = Vec::new();
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                result.push(byte);
+                i += 3;
+            }
+
+            None => {
+                result.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+// Links to other wiki pages look like href="./Page_title#Section"
+fn get_href_page(href: &str) -> Option<String> {
+    let page = href.strip_prefix("./")?;
+    get_link_page(&percent_decode(page).replace('_', " "))
+}
+
+// Text of all descendants, for text that is shown in boxes
+fn get_inline_text(node: &NodeRef) -> String {
+    let mut result = String::new();
+
+    for child in node.children() {
+        if is_hidden(&child) || is_reference(&child) {
+            continue;
+        }
+
+        if let Some(text) = child.as_text() {
+            result.push_str(&text.borrow());
+        } else if element_name(&child) == Some("br") {
+            result.push(' ');
+        } else {
+            result.push_str(&get_inline_text(&child));
+        }
+    }
+
+    result
+}
+
+// Pages are split into nested <section> elements, of which only the
+// content matters here, as the headings are part of it
+fn get_top_level_nodes(node: &NodeRef, result: &mut Vec<NodeRef>) {
+    for child in node.children() {
+        if element_name(&child) == Some("section") {
+            get_top_level_nodes(&child, result);
+        } else {
+            result.push(child);
+        }
+    }
+}
+
+struct HtmlParser<'c> {
+    config: &'c ParserConfig,
+
+    // Text of the references, by the id of their item in the list
+    references: HashMap<String, String>,
+
+    notes: Vec<Vec<Token>>,
+}
+
+impl<'c> HtmlParser<'c> {
+    fn new(config: &'c ParserConfig, document: &NodeRef) -> Self {
+        let mut references = HashMap::new();
+
+        if let Ok(items) = document.select("ol.mw-references > li") {
+            for item in items {
+                let item = item.as_node();
+
+                if let Some(id) = attribute(item, "id") {
+                    let text = match item.select_first(".mw-reference-text") {
+                        Ok(text) => get_inline_text(text.as_node()),
+                        Err(()) => get_inline_text(item),
+                    };
+                    references.insert(id, collapse_whitespace(&text));
+                }
+            }
+        }
+
+        HtmlParser { config, references, notes: Vec::new() }
+    }
+
+    fn tokens(&self, text: &str) -> Vec<Token> {
+        chop_into_tokens(self.config, text, Span::default())
+    }
+
+    fn add_note(&mut self, reference: &NodeRef) {
+        if !self.config.collect_notes {
+            return;
+        }
+
+        let note = reference
+            .select_first("a")
+            .ok()
+            .and_then(|link| attribute(link.as_node(), "href"))
+            .and_then(|href| href.split_once('#').map(|(_, id)| percent_decode(id)))
+            .and_then(|id| self.references.get(&id))
+            .filter(|note| !note.is_empty())
+            .cloned();
+
+        if let Some(note) = note {
+            let tokens = self.tokens(&note);
+            self.notes.push(tokens);
+        }
+    }
+
+    // Text in boxes, like captions and table cells, is read without its
+    // references, so their notes are added here
+    fn collect_notes(&mut self, node: &NodeRef) {
+        for descendant in node.descendants() {
+            if is_reference(&descendant) {
+                self.add_note(&descendant);
+            }
+        }
+    }
+
+    fn push_inline(&mut self, node: &NodeRef, current_para: &mut StyledText) {
+        if is_hidden(node) {
+            return;
+        }
+
+        if let Some(text) = node.as_text() {
+            current_para.push_str(&text.borrow());
+            return;
+        }
+
+        if is_reference(node) {
+            self.add_note(node);
+            return;
+        }
+
+        let saved_markup = current_para.markup.clone();
+
+        match element_name(node) {
+            Some("a") => {
+                let is_wiki_link = attribute(node, "rel").is_some_and(|rel| rel.contains("mw:WikiLink"));
+
+                if is_wiki_link {
+                    current_para.markup.link = attribute(node, "href").and_then(|href| get_href_page(&href));
+                }
+            }
+
+            Some("b" | "strong") => current_para.markup.bold = true,
+
+            Some("br") => current_para.push_line_break(),
+
+            Some("i" | "em") => current_para.markup.italic = true,
+
+            _ => { }
+        }
+
+        for child in node.children() {
+            self.push_inline(&child, current_para);
+        }
+
+        current_para.markup = saved_markup;
+    }
+
+    fn get_definition_list(&mut self, node: &NodeRef) -> Section {
+        let mut entries: Vec<(Vec<Token>, Vec<Vec<Section>>)> = Vec::new();
+
+        for child in node.children() {
+            match element_name(&child) {
+                Some("dt") => {
+                    self.collect_notes(&child);
+                    let term = collapse_whitespace(&get_inline_text(&child));
+                    entries.push((self.tokens(&term), Vec::new()));
+                }
+
+                Some("dd") => {
+                    // Lines starting with ":" are often just indented, without a term
+                    if entries.is_empty() {
+                        entries.push((Vec::new(), Vec::new()));
+                    }
+
+                    let details = self.get_sections(child.children());
+                    entries.last_mut().unwrap().1.push(details);
+                }
+
+                _ => { }
+            }
+        }
+
+        Section::DefinitionList(entries)
+    }
+
+    fn get_list_items(&mut self, node: &NodeRef) -> Vec<Vec<Section>> {
+        node.children()
+            .filter(|child| element_name(child) == Some("li"))
+            .map(|item| self.get_sections(item.children()))
+            .collect()
+    }
+
+    // Each image of a gallery has its caption in a "gallerytext" element
+    fn get_gallery_captions(&mut self, node: &NodeRef) -> Vec<Section> {
+        let Ok(captions) = node.select(".gallerytext") else { return Vec::new() };
+        let mut result = Vec::new();
+
+        for caption in captions {
+            self.collect_notes(caption.as_node());
+
+            let caption = collapse_whitespace(&get_inline_text(caption.as_node()));
+            if !caption.is_empty() {
+                result.push(Section::Figure(self.tokens(&caption)));
+            }
+        }
+
+        result
+    }
+
+    // Rows with a label and a value, others hold images or headers
+    fn get_infobox_rows(&mut self, node: &NodeRef) -> Vec<(Vec<Token>, Vec<Token>)> {
+        let Ok(rows) = node.select("tr") else { return Vec::new() };
+        let mut result = Vec::new();
+
+        for row in rows {
+            let label = row.as_node().children().find(|cell| element_name(cell) == Some("th"));
+            let value = row.as_node().children().find(|cell| element_name(cell) == Some("td"));
+            let (Some(label), Some(value)) = (label, value) else { continue };
+
+            let label = collapse_whitespace(&get_inline_text(&label));
+            let value_text = collapse_whitespace(&get_inline_text(&value));
+
+            if !label.is_empty() && !value_text.is_empty() {
+                self.collect_notes(&value);
+                result.push((self.tokens(&label), self.tokens(&value_text)));
+            }
+        }
+
+        result
+    }
+
+    fn get_table(&mut self, node: &NodeRef) -> Option<Section> {
+        // The caption and cells are read without their references
+        self.collect_notes(node);
+
+        let caption = node
+            .children()
+            .find(|child| element_name(child) == Some("caption"))
+            .map(|caption| collapse_whitespace(&get_inline_text(&caption)))
+            .filter(|caption| !caption.is_empty())
+            .map(|caption| self.tokens(&caption));
+
+        let rows: Vec<Vec<TableCell>> = node
+            .select("tr")
+            .map(|rows| {
+                rows.map(|row| {
+                        row.as_node()
+                            .children()
+                            .filter(|cell| matches!(element_name(cell), Some("th" | "td")))
+                            .map(|cell| TableCell {
+                                header: element_name(&cell) == Some("th"),
+                                tokens: self.tokens(&collapse_whitespace(&get_inline_text(&cell))),
+                            })
+                            .collect::<Vec<TableCell>>()
+                    })
+                    .filter(|cells| !cells.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if rows.is_empty() {
+            return None;
+        }
+
+        Some(Section::Table(caption, rows))
+    }
+
+    fn get_sections(&mut self, nodes: impl IntoIterator<Item = NodeRef>) -> Vec<Section> {
+        let config = self.config;
+
+        let mut result: Vec<Section> = Vec::new();
+        let mut current_para = StyledText::default();
+
+        for node in nodes {
+            if is_hidden(&node) {
+                continue;
+            }
+
+            let Some(name) = element_name(&node) else {
+                self.push_inline(&node, &mut current_para);
+                continue;
+            };
+
+            if let Some(level) = heading_level(&node) {
+                end_paragraph(config, &mut result, &mut current_para);
+
+                self.collect_notes(&node);
+                let heading_text = get_inline_text(&node);
+                result.push(Section::Heading(level as usize, self.tokens(&heading_text)));
+                continue;
+            }
+
+            match name {
+                "blockquote" => {
+                    end_paragraph(config, &mut result, &mut current_para);
+                    result.push(Section::Quote(self.get_sections(node.children())));
+                }
+
+                "dl" => {
+                    end_paragraph(config, &mut result, &mut current_para);
+                    result.push(self.get_definition_list(&node));
+                }
+
+                "figure" => {
+                    end_paragraph(config, &mut result, &mut current_para);
+
+                    self.collect_notes(&node);
+
+                    let caption = node
+                        .children()
+                        .find(|child| element_name(child) == Some("figcaption"))
+                        .map(|caption| collapse_whitespace(&get_inline_text(&caption)))
+                        .unwrap_or_default();
+
+                    if !caption.is_empty() {
+                        result.push(Section::Figure(self.tokens(&caption)));
+                    }
+                }
+
+                "ol" => {
+                    end_paragraph(config, &mut result, &mut current_para);
+                    result.push(Section::OrderedList(self.get_list_items(&node)));
+                }
+
+                "p" => {
+                    end_paragraph(config, &mut result, &mut current_para);
+
+                    for child in node.children() {
+                        self.push_inline(&child, &mut current_para);
+                    }
+
+                    end_paragraph(config, &mut result, &mut current_para);
+                }
+
+                "pre" => {
+                    end_paragraph(config, &mut result, &mut current_para);
+                    result.push(Section::Preformatted(self.tokens(&node.text_contents())));
+                }
+
+                "table" => {
+                    end_paragraph(config, &mut result, &mut current_para);
+
+                    if has_class(&node, "infobox") {
+                        let rows = self.get_infobox_rows(&node);
+                        if !rows.is_empty() {
+                            result.push(Section::Infobox(rows));
+                        }
+                    } else if let Some(table) = self.get_table(&node) {
+                        result.push(table);
+                    }
+                }
+
+                "ul" if has_class(&node, "gallery") => {
+                    end_paragraph(config, &mut result, &mut current_para);
+                    result.extend(self.get_gallery_captions(&node));
+                }
+
+                "ul" => {
+                    end_paragraph(config, &mut result, &mut current_para);
+                    result.push(Section::UnorderedList(self.get_list_items(&node)));
+                }
+
+                // Blocks like <div> just hold more blocks
+                "center" | "div" | "section" => {
+                    end_paragraph(config, &mut result, &mut current_para);
+                    result.extend(self.get_sections(node.children()));
+                }
+
+                _ => {
+                    self.push_inline(&node, &mut current_para);
+                }
+            }
+        }
+
+        end_paragraph(config, &mut result, &mut current_para);
+
+        result
+    }
+}
+
+// The displayed title, from the <title> element Parsoid puts in the head
+pub fn title(html: &str) -> Option<String> {
+    let document = kuchiki::parse_html().one(html);
+
+    document
+        .select_first("title")
+        .ok()
+        .map(|title| collapse_whitespace(&title.text_contents()))
+        .filter(|title| !title.is_empty())
+}
+
+pub fn is_disambiguation(html: &str) -> bool {
+    let document = kuchiki::parse_html().one(html);
+
+    document
+        .select_first(r#"meta[property="mw:PageProp/disambiguation"]"#)
+        .is_ok()
+}
+
+pub fn parse(config: &ParserConfig, language: &str, html: &str) -> (Vec<Section>, Vec<Vec<Token>>) {
+    let document = kuchiki::parse_html().one(html);
+    let body = document
+        .select_first("body")
+        .map(|body| body.as_node().clone())
+        .unwrap_or_else(|()| document.clone());
+
+    let mut nodes = Vec::new();
+    get_top_level_nodes(&body, &mut nodes);
+
+    let nodes = section_filter::filter(config, language, nodes, |node| {
+        heading_level(node).map(|level| (level, get_inline_text(node)))
+    });
+
+    let mut parser = HtmlParser::new(config, &document);
+    let content = parser.get_sections(nodes);
+
+    (content, parser.notes)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::article_parser::tests::{section_texts, text};
+
+    // Pages written by hand in the shape of Parsoid output, not saved ones
+    const CAT: &str = include_str!("../../tests/fixtures/cat.html");
+    const MERCURY: &str = include_str!("../../tests/fixtures/mercury.html");
+
+    fn parse_cat(config: &ParserConfig) -> (Vec<Section>, Vec<Vec<Token>>) {
+        parse(config, "en", CAT)
+    }
+
+    #[test]
+    fn page_properties() {
+        assert_eq!(title(CAT).as_deref(), Some("Cat"));
+        assert_eq!(title(MERCURY).as_deref(), Some("Mercury"));
+        assert!(!is_disambiguation(CAT));
+        assert!(is_disambiguation(MERCURY));
+    }
+
+    #[test]
+    fn headings_skip_appendices() {
+        let (content, _) = parse_cat(&ParserConfig::default());
+
+        let headings: Vec<(usize, String)> = content
+            .iter()
+            .filter_map(|section| match section {
+                Section::Heading(level, tokens) => Some((*level, text(tokens))),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(headings, vec![
+            (2, String::from("Etymology and naming")),
+            (2, String::from("History")),
+            (3, String::from("Early")),
+        ]);
+    }
+
+    #[test]
+    fn paragraphs_keep_markup_and_drop_boxes() {
+        let (content, _) = parse_cat(&ParserConfig::default());
+
+        let Some(Section::Paragraph(tokens)) = content.iter().find(|section| matches!(section, Section::Paragraph(_))) else {
+            panic!("no paragraph in {:?}", content);
+        };
+
+        assert_eq!(text(tokens), "The cat (Felis catus), commonly called domestic cat, is a small domesticated \
+            carnivorous mammal. It is the only species in the family Felidae.\nNew line after break.");
+
+        let cat = tokens.iter().find(|token| token.get_str() == "cat").unwrap();
+        assert!(cat.markup().bold);
+
+        let domesticated = tokens.iter().find(|token| token.get_str() == "domesticated").unwrap();
+        assert_eq!(domesticated.markup().link.as_deref(), Some("Domestication"));
+
+        let felidae = tokens.iter().find(|token| token.get_str() == "Felidae").unwrap();
+        assert_eq!(felidae.markup().link.as_deref(), Some("Felidae"));
+
+        // Link targets are percent decoded
+        let felicette = content.iter().flat_map(Section::tokens).find(|token| token.get_str() == "Félicette").unwrap();
+        assert_eq!(felicette.markup().link.as_deref(), Some("Félicette"));
+
+        // The hatnote, short description and navbox are not part of the article
+        let texts = section_texts(&content);
+        assert!(!texts.iter().any(|text| text.contains("disambiguation") || text.contains("Navbox")));
+    }
+
+    #[test]
+    fn infobox_rows() {
+        let (content, _) = parse_cat(&ParserConfig::default());
+
+        let Some(Section::Infobox(rows)) = content.first() else {
+            panic!("no infobox in {:?}", content);
+        };
+
+        let rows: Vec<(String, String)> = rows.iter().map(|(label, value)| (text(label), text(value))).collect();
+        assert!(rows.contains(&(String::from("Kingdom:"), String::from("Animalia"))));
+        assert!(rows.contains(&(String::from("Family:"), String::from("Felidae"))));
+    }
+
+    #[test]
+    fn lists() {
+        let (content, _) = parse_cat(&ParserConfig::default());
+
+        let Some(Section::UnorderedList(items)) = content.iter().find(|section| matches!(section, Section::UnorderedList(_))) else {
+            panic!("no list in {:?}", content);
+        };
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(section_texts(&items[0]), vec!["First item with bold"]);
+        assert_eq!(section_texts(&items[1])[0], "Second item");
+        assert!(matches!(&items[1][1], Section::UnorderedList(nested) if section_texts(&nested[0]) == vec!["Nested"]));
+
+        let Some(Section::DefinitionList(entries)) = content.iter().find(|section| matches!(section, Section::DefinitionList(_))) else {
+            panic!("no definition list in {:?}", content);
+        };
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(text(&entries[0].0), "Term");
+        assert_eq!(entries[0].1.iter().map(|details| section_texts(details).concat()).collect::<Vec<_>>(), vec!["Its details", "More details"]);
+    }
+
+    #[test]
+    fn tables() {
+        let (content, _) = parse_cat(&ParserConfig::default());
+
+        let Some(Section::Table(caption, rows)) = content.iter().find(|section| matches!(section, Section::Table(..))) else {
+            panic!("no table in {:?}", content);
+        };
+
+        assert_eq!(caption.as_ref().map(text).as_deref(), Some("Sizes"));
+
+        let cells: Vec<Vec<(bool, String)>> = rows
+            .iter()
+            .map(|row| row.iter().map(|cell| (cell.header, text(&cell.tokens))).collect())
+            .collect();
+        assert_eq!(cells, vec![
+            vec![(true, String::from("Name")), (true, String::from("Size"))],
+            vec![(false, String::from("Lion")), (false, String::from("Big"))],
+        ]);
+    }
+
+    #[test]
+    fn figures_and_galleries() {
+        let (content, _) = parse_cat(&ParserConfig::default());
+
+        let captions: Vec<String> = content
+            .iter()
+            .filter_map(|section| match section {
+                Section::Figure(tokens) => Some(text(tokens)),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(captions, vec!["A tabby cat", "Gallery caption one"]);
+    }
+
+    #[test]
+    fn quotes_and_preformatted_text() {
+        let (content, _) = parse_cat(&ParserConfig::default());
+
+        assert!(content.iter().any(|section| matches!(section, Section::Quote(quote) if section_texts(quote) == vec!["Quoted words here."])));
+        assert!(content.iter().any(|section| matches!(section, Section::Preformatted(tokens) if text(tokens) == "code  block\nsecond line")));
+    }
+
+    #[test]
+    fn references_become_notes() {
+        let (content, notes) = parse_cat(&ParserConfig::default());
+        assert!(notes.is_empty());
+        assert!(!section_texts(&content).iter().any(|text| text.contains("[1]")));
+
+        let config = ParserConfig { collect_notes: true, ..Default::default() };
+        let (_, notes) = parse_cat(&config);
+        assert_eq!(notes.iter().map(text).collect::<Vec<_>>(), vec![
+            "Family note.",
+            "Linnaeus, C. (1758). Systema naturae.",
+            "Caption note.",
+            "Heading note.",
+            "Cell note.",
+        ]);
+    }
+}
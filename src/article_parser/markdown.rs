@@ -0,0 +1,694 @@
+use super::{chop_into_tokens, end_paragraph, section_filter};
+use super::{ParserConfig, Section, Span, StyledText, TableCell, Token};
+
+// Markdown as people usually write it: headings, lists, quotes, fenced
+// code and tables, with bold, italic and links in the text. Indented code
+// blocks and HTML other than <br> are not understood and stay text.
+// Plain text files only have paragraphs.
+
+// A line of the file without its line ending, and where it starts
+#[derive(Clone, Copy)]
+struct Line<'t> {
+    text: &'t str,
+    start: usize,
+}
+
+impl<'t> Line<'t> {
+    fn span(&self) -> Span {
+        Span { start: self.start, end: self.start + self.text.len() }
+    }
+
+    fn is_blank(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+
+    fn indent(&self) -> usize {
+        self.text.len() - self.text.trim_start_matches(' ').len()
+    }
+
+    // Without the first `count` bytes, which are spaces or markers
+    fn skip(&self, count: usize) -> Line<'t> {
+        let count = count.min(self.text.len());
+        Line { text: &self.text[count..], start: self.start + count }
+    }
+
+    fn trim(&self) -> Line<'t> {
+        let text = self.text.trim_start();
+        let start = self.start + self.text.len() - text.len();
+        Line { text: text.trim_end(), start }
+    }
+}
+
+fn split_lines(content: &str) -> Vec<Line<'_>> {
+    let mut start = 0;
+
+    content
+        .split_inclusive('\n')
+        .map(|line| {
+            let text = line.trim_end_matches(['\n', '\r']);
+            let result = Line { text, start };
+            start += line.len();
+            result
+        })
+        .collect()
+}
+
+// Level and text of "## Heading", closing hashes are optional
+fn atx_heading(line: Line) -> Option<(usize, Line)> {
+    let line = line.trim();
+    let level = line.text.chars().take_while(|c| *c == '#').count();
+
+    // Like "#hashtag"
+    let rest = line.skip(level);
+    if level == 0 || level > 6 || !(rest.text.is_empty() || rest.text.starts_with([' ', '\t'])) {
+        return None;
+    }
+
+    let mut text = rest.trim();
+    let without_hashes = text.text.trim_end_matches('#');
+    if without_hashes.is_empty() || without_hashes.ends_with(' ') {
+        text.text = without_hashes.trim_end();
+    }
+
+    Some((level, text))
+}
+
+// Underlines of the text above, which makes it a heading
+fn setext_level(line: Line) -> Option<usize> {
+    let text = line.text.trim();
+
+    if text.is_empty() {
+        None
+    } else if text.chars().all(|c| c == '=') {
+        Some(1)
+    } else if text.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+// Like "---" or "* * *"
+fn is_thematic_break(line: Line) -> bool {
+    let chars: Vec<char> = line.text.chars().filter(|c| !c.is_whitespace()).collect();
+
+    chars.len() >= 3 && ['-', '*', '_'].iter().any(|marker| chars.iter().all(|c| c == marker))
+}
+
+// The backticks or tildes that open a code block, which close it again
+fn code_fence(line: Line<'_>) -> Option<&str> {
+    let text = line.text.trim_start();
+
+    ['`', '~'].into_iter().find_map(|marker| {
+        let length = text.chars().take_while(|c| *c == marker).count();
+        (length >= 3).then(|| &text[..length])
+    })
+}
+
+fn is_quote(line: Line) -> bool {
+    line.text.trim_start().starts_with('>')
+}
+
+// Width of the marker of a list item, like "- " or "12. ", including the
+// indentation, and whether the list is numbered
+fn list_marker(line: Line) -> Option<(usize, bool)> {
+    let indent = line.indent();
+    let rest = &line.text[indent..];
+
+    let (length, ordered) = if rest.starts_with(['-', '*', '+']) {
+        (1, false)
+    } else {
+        let digits = rest.chars().take_while(char::is_ascii_digit).count();
+        if digits == 0 || digits > 9 || !rest[digits..].starts_with(['.', ')']) {
+            return None;
+        }
+        (digits + 1, true)
+    };
+
+    match &rest[length..] {
+        "" => Some((indent + length, ordered)),
+        after if after.starts_with(' ') => Some((indent + length + 1, ordered)),
+        _ => None,
+    }
+}
+
+fn starts_block(line: Line) -> bool {
+    atx_heading(line).is_some() || code_fence(line).is_some() || is_quote(line)
+        || is_thematic_break(line) || list_marker(line).is_some()
+}
+
+// Cells of a table row like "| a | b |", the outer pipes are optional
+fn table_cells(line: Line) -> Vec<Line> {
+    let line = line.trim();
+    let mut cells = Vec::new();
+    let mut cell_start = 0;
+    let mut previous = ' ';
+
+    for (i, c) in line.text.char_indices() {
+        if c == '|' && previous != '\\' {
+            cells.push(Line { text: &line.text[cell_start..i], start: line.start + cell_start });
+            cell_start = i + 1;
+        }
+        previous = c;
+    }
+    cells.push(line.skip(cell_start));
+
+    if line.text.starts_with('|') {
+        cells.remove(0);
+    }
+    if line.text.ends_with('|') && line.text.len() > 1 {
+        cells.pop();
+    }
+
+    cells.iter().map(Line::trim).collect()
+}
+
+// The line under the header, like "|---|:--:|"
+fn is_table_delimiter(line: Line) -> bool {
+    line.text.contains('|') && table_cells(line).iter().all(|cell| {
+        cell.text.contains('-') && cell.text.chars().all(|c| c == '-' || c == ':')
+    })
+}
+
+fn is_table_start(lines: &[Line], i: usize) -> bool {
+    lines[i].text.contains('|') && lines.get(i + 1).is_some_and(|next| is_table_delimiter(*next))
+}
+
+// Length of the run of `c` at the start of `chars`
+fn run_length(chars: &[(usize, char)], c: char) -> usize {
+    chars.iter().take_while(|(_, d)| *d == c).count()
+}
+
+// Pushes the text of a single line, with the markup its markers give
+fn push_inline(text: &mut StyledText, line: Line) {
+    let chars: Vec<(usize, char)> = line.text.char_indices().collect();
+    let byte_at = |i: usize| chars.get(i).map_or(line.text.len(), |(at, _)| *at);
+
+    let mut plain_start = 0;
+    let mut i = 0;
+
+    // Text up to the marker at `end` that is pushed as it is
+    let flush = |text: &mut StyledText, plain_start: usize, end: usize| {
+        if plain_start < end {
+            text.span = Span { start: line.start + plain_start, end: line.start + end };
+            text.push_str(&line.text[plain_start..end]);
+        }
+    };
+
+    while i < chars.len() {
+        let (at, c) = chars[i];
+        let previous = i.checked_sub(1).map(|p| chars[p].1);
+
+        match c {
+            '\\' if chars.get(i + 1).is_some_and(|(_, next)| next.is_ascii_punctuation()) => {
+                flush(text, plain_start, at);
+                plain_start = byte_at(i + 1);
+                i += 2;
+            }
+
+            '`' => {
+                let length = run_length(&chars[i..], '`');
+                let closing = (i + length..chars.len())
+                    .find(|j| run_length(&chars[*j..], '`') == length && chars[j - 1].1 != '`');
+
+                match closing {
+                    Some(end) => {
+                        flush(text, plain_start, at);
+                        let code = Line { text: &line.text[byte_at(i + length)..byte_at(end)], start: line.start + byte_at(i + length) };
+                        text.span = code.span();
+                        text.push_str(code.text.trim());
+
+                        i = end + length;
+                        plain_start = byte_at(i);
+                    }
+
+                    None => i += length,
+                }
+            }
+
+            '*' | '_' => {
+                let length = run_length(&chars[i..], c);
+                let next = chars.get(i + length).map(|(_, next)| *next);
+
+                let can_open = next.is_some_and(|next| !next.is_whitespace());
+                let can_close = previous.is_some_and(|previous| !previous.is_whitespace());
+
+                // Like snake_case, where underscores are not markup
+                let in_word = c == '_' && previous.is_some_and(char::is_alphanumeric) && next.is_some_and(char::is_alphanumeric);
+
+                let toggles = |on: bool| if on { can_close } else { can_open };
+                let bold = length >= 2 && !in_word && toggles(text.markup.bold);
+                let italic = length != 2 && !in_word && toggles(text.markup.italic);
+
+                if bold || italic {
+                    flush(text, plain_start, at);
+                    text.markup.bold ^= bold;
+                    text.markup.italic ^= italic;
+                    plain_start = byte_at(i + length);
+                }
+                i += length;
+            }
+
+            // Images are not shown, like in articles
+            '!' if chars.get(i + 1).is_some_and(|(_, next)| *next == '[') => {
+                match link_end(&chars, i + 1) {
+                    Some((_, end)) => {
+                        flush(text, plain_start, at);
+                        i = end;
+                        plain_start = byte_at(i);
+                    }
+
+                    None => i += 1,
+                }
+            }
+
+            '[' => {
+                match link_end(&chars, i) {
+                    Some((text_end, end)) => {
+                        flush(text, plain_start, at);
+
+                        let start = byte_at(i + 1);
+                        push_inline(text, Line { text: &line.text[start..byte_at(text_end)], start: line.start + start });
+
+                        i = end;
+                        plain_start = byte_at(i);
+                    }
+
+                    None => i += 1,
+                }
+            }
+
+            '<' if line.text[at..].starts_with("<br") => {
+                match line.text[at..].find('>') {
+                    Some(length) => {
+                        flush(text, plain_start, at);
+                        text.push_line_break();
+                        plain_start = at + length + 1;
+                        i = chars.iter().position(|(byte, _)| *byte >= plain_start).unwrap_or(chars.len());
+                    }
+
+                    None => i += 1,
+                }
+            }
+
+            _ => i += 1,
+        }
+    }
+
+    flush(text, plain_start, line.text.len());
+}
+
+// For "[text](url)" starting at `start`, the index of the closing bracket
+// and of the character after the link
+fn link_end(chars: &[(usize, char)], start: usize) -> Option<(usize, usize)> {
+    let mut depth = 0;
+    let text_end = (start..chars.len()).find(|i| {
+        match chars[*i].1 {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ => { }
+        }
+        depth == 0
+    })?;
+
+    if chars.get(text_end + 1).map(|(_, c)| *c) != Some('(') {
+        return None;
+    }
+
+    let url_end = (text_end + 2..chars.len()).find(|i| chars[*i].1 == ')')?;
+    Some((text_end, url_end + 1))
+}
+
+// Lines of a paragraph are joined with spaces, unless they end in two
+// spaces or a backslash, which breaks the line
+fn push_lines(text: &mut StyledText, lines: &[Line], markdown: bool) {
+    let mut previous: Option<(Line, bool)> = None;
+
+    for line in lines {
+        let hard_break = markdown && (line.text.ends_with("  ") || line.text.ends_with('\\'));
+        let mut content = line.trim();
+        if markdown && content.text.ends_with('\\') {
+            content.text = &content.text[..content.text.len() - 1];
+        }
+
+        if let Some((previous, previous_break)) = previous {
+            if previous_break {
+                text.push_line_break();
+            } else {
+                text.span = Span { start: previous.span().end, end: content.start };
+                text.push_str(" ");
+            }
+        }
+
+        if markdown {
+            push_inline(text, content);
+        } else {
+            text.span = content.span();
+            text.push_str(content.text);
+        }
+
+        previous = Some((content, hard_break));
+    }
+}
+
+fn inline_tokens(config: &ParserConfig, lines: &[Line]) -> Vec<Token> {
+    let mut text = StyledText::default();
+    push_lines(&mut text, lines, true);
+    text.tokens(config)
+}
+
+// Text without the markers, for titles
+fn plain_text(lines: &[Line]) -> String {
+    let mut text = StyledText::default();
+    push_lines(&mut text, lines, true);
+
+    let plain: String = text.runs.iter().map(|(run, ..)| run.as_str()).collect();
+    plain.trim().to_string()
+}
+
+fn end_markdown_paragraph(config: &ParserConfig, result: &mut Vec<Section>, paragraph: &mut Vec<Line>) {
+    let mut text = StyledText::default();
+    push_lines(&mut text, paragraph, true);
+    end_paragraph(config, result, &mut text);
+
+    paragraph.clear();
+}
+
+// The items of the list at `lines[start]`, and the index of the line
+// after the list
+fn get_list(config: &ParserConfig, lines: &[Line], start: usize) -> (Section, usize) {
+    let (_, ordered) = list_marker(lines[start]).unwrap();
+
+    let mut items: Vec<Vec<Line>> = Vec::new();
+    let mut content_indent = 0;
+
+    let mut i = start;
+    while i < lines.len() {
+        let line = lines[i];
+
+        let new_item = list_marker(line)
+            .filter(|(_, o)| *o == ordered)
+            .filter(|_| items.is_empty() || line.indent() < content_indent);
+
+        if let Some((width, _)) = new_item {
+            content_indent = width;
+            items.push(vec![line.skip(width)]);
+            i += 1;
+            continue;
+        }
+
+        if line.is_blank() {
+            // Blank lines are part of the list if it goes on after them
+            let goes_on = lines[i + 1..]
+                .iter()
+                .find(|next| !next.is_blank())
+                .is_some_and(|next| {
+                    next.indent() >= content_indent || list_marker(*next).is_some_and(|(_, o)| o == ordered)
+                });
+
+            if !goes_on {
+                break;
+            }
+        } else if line.indent() < content_indent && (starts_block(line) || lines[i - 1].is_blank()) {
+            break;
+        }
+
+        // Lines of a paragraph may go on without indentation
+        items.last_mut().unwrap().push(line.skip(line.indent().min(content_indent)));
+        i += 1;
+    }
+
+    let items = items.iter().map(|item| get_sections(config, item)).collect();
+    let list = if ordered { Section::OrderedList(items) } else { Section::UnorderedList(items) };
+
+    (list, i)
+}
+
+// The table at `lines[start]`, and the index of the line after it
+fn get_table(config: &ParserConfig, lines: &[Line], start: usize) -> (Section, usize) {
+    let mut rows: Vec<Vec<TableCell>> = Vec::new();
+
+    let header = table_cells(lines[start])
+        .iter()
+        .map(|cell| TableCell { header: true, tokens: inline_tokens(config, &[*cell]) })
+        .collect();
+    rows.push(header);
+
+    let mut i = start + 2;
+    while i < lines.len() && !lines[i].is_blank() && lines[i].text.contains('|') {
+        let cells = table_cells(lines[i])
+            .iter()
+            .map(|cell| TableCell { header: false, tokens: inline_tokens(config, &[*cell]) })
+            .collect();
+        rows.push(cells);
+        i += 1;
+    }
+
+    (Section::Table(None, rows), i)
+}
+
+fn get_sections(config: &ParserConfig, lines: &[Line]) -> Vec<Section> {
+    let mut result: Vec<Section> = Vec::new();
+    let mut paragraph: Vec<Line> = Vec::new();
+
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+
+        if line.is_blank() {
+            end_markdown_paragraph(config, &mut result, &mut paragraph);
+            i += 1;
+            continue;
+        }
+
+        // A line of "---" under text is a heading, not a break
+        if !paragraph.is_empty() {
+            if let Some(level) = setext_level(line) {
+                result.push(Section::Heading(level, inline_tokens(config, &paragraph)));
+                paragraph.clear();
+                i += 1;
+                continue;
+            }
+        }
+
+        if let Some(fence) = code_fence(line) {
+            end_markdown_paragraph(config, &mut result, &mut paragraph);
+
+            let end = (i + 1..lines.len())
+                .find(|j| lines[*j].text.trim_start().starts_with(fence))
+                .unwrap_or(lines.len());
+
+            let code = &lines[i + 1..end];
+            let text: String = code.iter().map(|line| line.text).intersperse("\n").collect();
+            let span = code.iter().fold(Span::default(), |span, line| span.union(line.span()));
+
+            if !text.trim().is_empty() {
+                result.push(Section::Preformatted(chop_into_tokens(config, &text, span)));
+            }
+
+            i = end + 1;
+            continue;
+        }
+
+        if let Some((level, text)) = atx_heading(line) {
+            end_markdown_paragraph(config, &mut result, &mut paragraph);
+            result.push(Section::Heading(level, inline_tokens(config, &[text])));
+            i += 1;
+            continue;
+        }
+
+        if is_thematic_break(line) {
+            end_markdown_paragraph(config, &mut result, &mut paragraph);
+            i += 1;
+            continue;
+        }
+
+        if is_quote(line) {
+            end_markdown_paragraph(config, &mut result, &mut paragraph);
+
+            let mut quoted = Vec::new();
+            while i < lines.len() && is_quote(lines[i]) {
+                let line = lines[i].skip(lines[i].indent() + 1);
+                quoted.push(if line.text.starts_with(' ') { line.skip(1) } else { line });
+                i += 1;
+            }
+
+            result.push(Section::Quote(get_sections(config, &quoted)));
+            continue;
+        }
+
+        if list_marker(line).is_some() {
+            end_markdown_paragraph(config, &mut result, &mut paragraph);
+
+            let (list, next) = get_list(config, lines, i);
+            result.push(list);
+            i = next;
+            continue;
+        }
+
+        if paragraph.is_empty() && is_table_start(lines, i) {
+            let (table, next) = get_table(config, lines, i);
+            result.push(table);
+            i = next;
+            continue;
+        }
+
+        paragraph.push(line);
+        i += 1;
+    }
+
+    end_markdown_paragraph(config, &mut result, &mut paragraph);
+
+    result
+}
+
+// A heading before anything else is the title of the text, and the
+// index of the line after it
+fn get_title(lines: &[Line]) -> Option<(String, usize)> {
+    let first = lines.iter().position(|line| !line.is_blank())?;
+
+    if let Some((_, text)) = atx_heading(lines[first]) {
+        return Some((plain_text(&[text]), first + 1));
+    }
+
+    let underline = *lines.get(first + 1)?;
+    setext_level(underline).map(|_| (plain_text(&lines[first..first + 1]), first + 2))
+}
+
+// The title of the text, if it has one, and its sections
+pub fn parse(config: &ParserConfig, language: &str, content: &str) -> (Option<String>, Vec<Section>) {
+    let lines = split_lines(content);
+
+    let (title, start) = match get_title(&lines) {
+        Some((title, start)) => (Some(title), start),
+        None => (None, 0),
+    };
+
+    let sections = get_sections(config, &lines[start..]);
+
+    let sections = section_filter::filter(config, language, sections, |section| match section {
+        Section::Heading(level, tokens) => Some((*level as u8, tokens.iter().map(Token::get_str).collect())),
+        _ => None,
+    });
+
+    (title, sections)
+}
+
+// Paragraphs are separated by blank lines
+pub fn parse_plain_text(config: &ParserConfig, content: &str) -> Vec<Section> {
+    let lines = split_lines(content);
+    let mut result = Vec::new();
+
+    for paragraph in lines.split(|line| line.is_blank()) {
+        let mut text = StyledText::default();
+        push_lines(&mut text, paragraph, false);
+        end_paragraph(config, &mut result, &mut text);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::article_parser::tests::{section_texts, text};
+
+    fn parse_markdown(content: &str) -> (Option<String>, Vec<Section>) {
+        parse(&ParserConfig::default(), "en", content)
+    }
+
+    fn words_with(sections: &[Section], has_markup: impl Fn(&Token) -> bool) -> Vec<&str> {
+        sections
+            .iter()
+            .flat_map(Section::tokens)
+            .filter(|token| matches!(token, Token::Word(..)) && has_markup(token))
+            .map(Token::get_str)
+            .collect()
+    }
+
+    #[test]
+    fn titles_and_headings() {
+        let (title, sections) = parse_markdown("# The *Cat*\n\nIntro.\n\n## Behavior ##\n\nSleeps.\n\nDiet\n----\n\nFish.");
+
+        assert_eq!(title.as_deref(), Some("The Cat"));
+        assert!(matches!(sections[1], Section::Heading(2, _)));
+        assert!(matches!(sections[3], Section::Heading(2, _)));
+        assert_eq!(section_texts(&sections), ["Intro.", "Behavior", "Sleeps.", "Diet", "Fish."]);
+
+        let (title, _) = parse_markdown("Cat\n===\n\nText.");
+        assert_eq!(title.as_deref(), Some("Cat"));
+
+        let (title, sections) = parse_markdown("Text first.\n\n# Heading");
+        assert_eq!(title, None);
+        assert_eq!(section_texts(&sections), ["Text first.", "Heading"]);
+    }
+
+    #[test]
+    fn emphasis_links_and_code() {
+        let (_, sections) = parse_markdown("A **bold** and _italic_ [link](https://example.org) ![image](cat.png) `co*de` in snake_case.");
+
+        assert_eq!(section_texts(&sections), ["A bold and italic link  co*de in snake_case."]);
+        assert_eq!(words_with(&sections, |token| token.markup().bold), ["bold"]);
+        assert_eq!(words_with(&sections, |token| token.markup().italic), ["italic"]);
+    }
+
+    #[test]
+    fn paragraphs_and_line_breaks() {
+        let (_, sections) = parse_markdown("One line\ngoes on.\n\nBroken  \nline\\\nagain<br>and \\*escaped\\*.");
+
+        assert_eq!(section_texts(&sections), ["One line goes on.", "Broken\nline\nagain\nand *escaped*."]);
+    }
+
+    #[test]
+    fn lists() {
+        let (_, sections) = parse_markdown("- first\n- second\n  goes on\n\n  with a paragraph\n\n1. one\n2. two\n\nAfter.");
+
+        let Section::UnorderedList(items) = &sections[0] else { panic!("{:?}", sections) };
+        assert_eq!(items.iter().map(|item| section_texts(item)).collect::<Vec<_>>(),
+            [vec!["first"], vec!["second goes on", "with a paragraph"]]);
+
+        let Section::OrderedList(items) = &sections[1] else { panic!("{:?}", sections) };
+        assert_eq!(items.len(), 2);
+
+        assert_eq!(section_texts(&sections[2..]), ["After."]);
+    }
+
+    #[test]
+    fn tables() {
+        let (_, sections) = parse_markdown("| Name | Legs |\n|------|-----:|\n| Cat  | 4    |\n| Bird | **2** |\n\nAfter.");
+
+        let Section::Table(None, rows) = &sections[0] else { panic!("{:?}", sections) };
+        let cells: Vec<Vec<(bool, String)>> = rows
+            .iter()
+            .map(|row| row.iter().map(|cell| (cell.header, text(&cell.tokens))).collect())
+            .collect();
+
+        assert_eq!(cells, [
+            [(true, String::from("Name")), (true, String::from("Legs"))],
+            [(false, String::from("Cat")), (false, String::from("4"))],
+            [(false, String::from("Bird")), (false, String::from("2"))],
+        ]);
+        assert_eq!(section_texts(&sections[1..]), ["After."]);
+    }
+
+    #[test]
+    fn code_and_quotes() {
+        let (_, sections) = parse_markdown("```rust\nlet  x = 1;\n\n# not a heading\n```\n\n> Quoted\n> text\n>\n> > nested\n\n---\n\nAfter.");
+
+        assert!(matches!(sections[0], Section::Preformatted(_)));
+        assert_eq!(section_texts(&sections[..1]), ["let  x = 1;\n\n# not a heading"]);
+
+        let Section::Quote(quoted) = &sections[1] else { panic!("{:?}", sections) };
+        assert_eq!(section_texts(&quoted[..1]), ["Quoted text"]);
+        assert!(matches!(&quoted[1], Section::Quote(nested) if section_texts(nested) == ["nested"]));
+
+        assert_eq!(section_texts(&sections[2..]), ["After."]);
+    }
+
+    #[test]
+    fn plain_text_is_not_markdown() {
+        let sections = parse_plain_text(&ParserConfig::default(), "# Not a heading\n**not bold**\n\nSecond.");
+
+        assert_eq!(section_texts(&sections), ["# Not a heading **not bold**", "Second."]);
+    }
+}
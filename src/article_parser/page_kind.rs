@@ -0,0 +1,115 @@
+use parse_wiki_text::{Configuration, Node};
+
+use super::{html, SourceFormat};
+
+// Disambiguation pages and lists are mostly links to other articles,
+// and make poor puzzles. Redirects, as saved from the wiki text of
+// their page, have no text at all.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PageKind {
+    Article,
+    Disambiguation,
+    List,
+    Redirect,
+}
+
+impl PageKind {
+    pub fn warning(&self) -> Option<&'static str> {
+        match self {
+            PageKind::Article => None,
+            PageKind::Disambiguation => Some("This is a disambiguation page, which makes a poor puzzle"),
+            PageKind::List => Some("This is a list article, which makes a poor puzzle"),
+            PageKind::Redirect => Some("This is only a redirect to another article, load that one instead"),
+        }
+    }
+}
+
+const DISAMBIGUATION_TEMPLATES: [&str; 14] = [
+    "disambiguation", "disambig", "disamb", "dab", "dmbox", "hndis", "geodis",
+    "set index article", "sia", "surname", "given name",
+    "begriffsklärung", "homonymie", "desambiguación",
+];
+
+const LIST_TEMPLATES: [&str; 2] = ["dynamic list", "incomplete list"];
+
+const LIST_TITLE_PREFIXES: [&str; 4] = ["List of ", "Lists of ", "Liste ", "Lista de "];
+
+// Only plain template names, templates building their names from
+// other templates are not worth the trouble here
+fn template_name(nodes: &[Node]) -> String {
+    let name: String = nodes
+        .iter()
+        .filter_map(|node| match node {
+            Node::Text { value, .. } => Some(*value),
+            _ => None,
+        })
+        .collect();
+
+    name.trim().to_lowercase().replace('_', " ")
+}
+
+fn is_disambiguation_template(name: &str) -> bool {
+    // Like "human name disambiguation"
+    DISAMBIGUATION_TEMPLATES.contains(&name) || name.ends_with(" disambiguation")
+}
+
+pub fn page_kind(title: &str, format: SourceFormat, content: &str) -> PageKind {
+    // Custom texts are whatever the players make of them
+    if matches!(format, SourceFormat::Markdown | SourceFormat::PlainText) {
+        return PageKind::Article;
+    }
+
+    if LIST_TITLE_PREFIXES.iter().any(|prefix| title.starts_with(prefix)) {
+        return PageKind::List;
+    }
+
+    // Parsoid marks the page itself, whichever template made it one
+    if format == SourceFormat::Html {
+        return if html::is_disambiguation(content) { PageKind::Disambiguation } else { PageKind::Article };
+    }
+
+    let parsed = Configuration::default().parse(content);
+
+    if let Some(Node::Redirect { .. }) = parsed.nodes.first() {
+        return PageKind::Redirect;
+    }
+
+    for node in &parsed.nodes {
+        match node {
+            Node::MagicWord { start, end } if content.get(*start..*end) == Some("__DISAMBIG__") => {
+                return PageKind::Disambiguation;
+            }
+
+            Node::Template { name, .. } => {
+                let name = template_name(name);
+
+                if is_disambiguation_template(&name) {
+                    return PageKind::Disambiguation;
+                }
+
+                if LIST_TEMPLATES.contains(&name.as_str()) {
+                    return PageKind::List;
+                }
+            }
+
+            _ => { }
+        }
+    }
+
+    PageKind::Article
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_of_wiki_text() {
+        assert!(page_kind("Cat", SourceFormat::Wikitext, "The cat is a mammal.") == PageKind::Article);
+        assert!(page_kind("Cats", SourceFormat::Wikitext, "#REDIRECT [[Cat]]") == PageKind::Redirect);
+        assert!(page_kind("Cats", SourceFormat::Wikitext, "#redirect [[Cat]]\n{{R from plural}}") == PageKind::Redirect);
+        assert!(page_kind("Mercury", SourceFormat::Wikitext, "Mercury may be:\n{{disambiguation}}") == PageKind::Disambiguation);
+        assert!(page_kind("List of cats", SourceFormat::Wikitext, "Cats.") == PageKind::List);
+        assert!(page_kind("Notes", SourceFormat::Markdown, "#REDIRECT [[Cat]]") == PageKind::Article);
+    }
+}
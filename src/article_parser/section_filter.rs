@@ -0,0 +1,144 @@
+use parse_wiki_text::Node;
+
+use super::{get_inline_text, ParserConfig};
+
+// How much of an article to play with, for shorter games
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum SectionLimit {
+    #[default]
+    All,
+    LeadOnly,
+
+    // The lead and this many sections after it
+    First(usize),
+}
+
+impl SectionLimit {
+    pub const CHOICES: [SectionLimit; 5] = [
+        SectionLimit::All,
+        SectionLimit::LeadOnly,
+        SectionLimit::First(1),
+        SectionLimit::First(3),
+        SectionLimit::First(5),
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            SectionLimit::All => String::from("whole article"),
+            SectionLimit::LeadOnly => String::from("lead only"),
+            SectionLimit::First(1) => String::from("lead + 1 section"),
+            SectionLimit::First(n) => format!("lead + {} sections", n),
+        }
+    }
+}
+
+// Headings of the sections at the end of articles that only hold
+// references and links, which are no fun to guess and may give away
+// the title
+const APPENDIX_HEADINGS: [(&str, &[&str]); 7] = [
+    ("en", &[
+        "references", "notes", "footnotes", "citations", "sources", "bibliography",
+        "notes and references", "further reading", "external links", "see also",
+    ]),
+    ("de", &[
+        "einzelnachweise", "anmerkungen", "quellen", "literatur", "weblinks", "siehe auch",
+    ]),
+    ("fr", &[
+        "notes et références", "références", "notes", "bibliographie", "liens externes",
+        "voir aussi", "articles connexes", "annexes",
+    ]),
+    ("es", &[
+        "referencias", "notas", "bibliografía", "enlaces externos", "véase también",
+    ]),
+    ("it", &[
+        "note", "bibliografia", "collegamenti esterni", "voci correlate",
+    ]),
+    ("nl", &[
+        "referenties", "bronnen", "noten", "literatuur", "externe links", "zie ook",
+    ]),
+    ("pt", &[
+        "referências", "notas", "bibliografia", "ligações externas", "ver também",
+    ]),
+];
+
+fn is_appendix_heading(language: &str, heading: &str) -> bool {
+    let heading = heading.trim().to_lowercase();
+
+    // Other languages get the English list, which is better than nothing
+    let headings = APPENDIX_HEADINGS
+        .iter()
+        .find(|(code, _)| *code == language)
+        .unwrap_or(&APPENDIX_HEADINGS[0])
+        .1;
+
+    headings.contains(&heading.as_str())
+}
+
+// What the section of collected notes is called in each language
+const NOTES_HEADINGS: [(&str, &str); 10] = [
+    ("de", "Anmerkungen"),
+    ("en", "Notes"),
+    ("es", "Notas"),
+    ("fr", "Notes"),
+    ("it", "Note"),
+    ("ja", "脚注"),
+    ("nl", "Noten"),
+    ("pt", "Notas"),
+    ("th", "เชิงอรรถ"),
+    ("zh", "注释"),
+];
+
+pub fn notes_heading(language: &str) -> &'static str {
+    NOTES_HEADINGS
+        .iter()
+        .find(|(code, _)| *code == language)
+        .map_or("Notes", |(_, heading)| heading)
+}
+
+// The top level items of the article that make it into the game, for
+// either kind of parser. `heading` gives the level and text of headings.
+pub fn filter<T>(config: &ParserConfig, language: &str, items: impl IntoIterator<Item = T>, heading: impl Fn(&T) -> Option<(u8, String)>) -> Vec<T> {
+    let mut result = Vec::new();
+    let mut sections = 0;
+
+    // Level of the heading of the appendix being left out
+    let mut skipped_level: Option<u8> = None;
+
+    for item in items {
+        if let Some((level, heading)) = heading(&item) {
+            // Only "== Heading ==" starts a new section, "=== Heading ===" is part of one
+            if level <= 2 {
+                sections += 1;
+            }
+
+            match config.section_limit {
+                SectionLimit::LeadOnly => break,
+                SectionLimit::First(n) if sections > n => break,
+                _ => { }
+            }
+
+            if skipped_level.is_some_and(|skipped| level > skipped) {
+                continue;
+            }
+            skipped_level = None;
+
+            if !config.keep_appendices && is_appendix_heading(language, &heading) {
+                skipped_level = Some(level);
+                continue;
+            }
+        } else if skipped_level.is_some() {
+            continue;
+        }
+
+        result.push(item);
+    }
+
+    result
+}
+
+pub fn filter_nodes<'a, 'b>(config: &ParserConfig, language: &str, nodes: &'b [Node<'a>]) -> Vec<&'b Node<'a>> {
+    filter(config, language, nodes, |node| match node {
+        Node::Heading { level, nodes, .. } => Some((*level, get_inline_text(config, nodes))),
+        _ => None,
+    })
+}
@@ -0,0 +1,277 @@
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{Span, StyledChar, Token};
+use super::dictionary_segmenter::is_unspaced;
+
+// Where words start and end
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum WordBoundaries {
+    // Runs of letters and digits
+    #[default]
+    Alphanumeric,
+
+    // Word boundaries of Unicode (UAX #29), which keep combining marks
+    // and numbers like "3.14" with their word
+    Unicode,
+}
+
+impl WordBoundaries {
+    pub const ALL: [WordBoundaries; 2] = [WordBoundaries::Alphanumeric, WordBoundaries::Unicode];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WordBoundaries::Alphanumeric => "letters and digits",
+            WordBoundaries::Unicode => "unicode words",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Segmentation {
+    pub boundaries: WordBoundaries,
+
+    // "don't" and "O'Brien" as one word
+    pub join_apostrophes: bool,
+
+    // "well-known" as one word
+    pub join_hyphens: bool,
+}
+
+impl Default for Segmentation {
+    fn default() -> Self {
+        Segmentation {
+            boundaries: WordBoundaries::default(),
+            join_apostrophes: true,
+            join_hyphens: false,
+        }
+    }
+}
+
+const APOSTROPHES: [char; 2] = ['\'', '’'];
+
+// Hyphen-minus and the Unicode hyphens, but not dashes
+const HYPHENS: [char; 3] = ['-', '‐', '‑'];
+
+// Ranges of characters, and whether they are a word
+type Piece = (Range<usize>, bool);
+
+fn alphanumeric_pieces(chars: &[char]) -> Vec<Piece> {
+    let mut result: Vec<Piece> = Vec::new();
+
+    for (i, c) in chars.iter().enumerate() {
+        let is_word = c.is_alphanumeric();
+
+        match result.last_mut() {
+            Some((range, word)) if *word == is_word => range.end = i + 1,
+            _ => result.push((i..i + 1, is_word)),
+        }
+    }
+
+    result
+}
+
+fn unicode_pieces(chars: &[char]) -> Vec<Piece> {
+    let text: String = chars.iter().collect();
+    let mut start = 0;
+
+    text.split_word_bounds()
+        .map(|segment| {
+            let end = start + segment.chars().count();
+            let piece = (start..end, segment.chars().any(char::is_alphanumeric));
+            start = end;
+            piece
+        })
+        .collect()
+}
+
+// Scripts written without spaces can change markup in the middle of what
+// looks like a single word here. Splitting there keeps the markup for the
+// dictionary, which finds the actual words later.
+fn split_at_markup(chars: &[StyledChar], pieces: Vec<Piece>) -> Vec<Piece> {
+    let mut result: Vec<Piece> = Vec::new();
+
+    for (range, is_word) in pieces {
+        let mut start = range.start;
+
+        for i in range.start + 1..range.end {
+            let (previous, previous_markup, _) = chars[i - 1];
+            let (current, markup, _) = chars[i];
+
+            if previous_markup != markup && (is_unspaced(previous) || is_unspaced(current)) {
+                result.push((start..i, is_word));
+                start = i;
+            }
+        }
+
+        result.push((start..range.end, is_word));
+    }
+
+    result
+}
+
+impl Segmentation {
+    fn is_joiner(&self, c: char) -> bool {
+        (self.join_apostrophes && APOSTROPHES.contains(&c)) || (self.join_hyphens && HYPHENS.contains(&c))
+    }
+
+    // Pieces with apostrophes and hyphens split off from words, so
+    // both kinds of boundaries treat them the same way
+    fn split_pieces(&self, chars: &[char]) -> Vec<Piece> {
+        let pieces = match self.boundaries {
+            WordBoundaries::Alphanumeric => alphanumeric_pieces(chars),
+            WordBoundaries::Unicode => unicode_pieces(chars),
+        };
+
+        let mut result: Vec<Piece> = Vec::new();
+
+        for (range, is_word) in pieces {
+            if !is_word {
+                result.push((range, false));
+                continue;
+            }
+
+            let mut start = range.start;
+            for i in range.clone() {
+                if APOSTROPHES.contains(&chars[i]) || HYPHENS.contains(&chars[i]) {
+                    if start < i {
+                        result.push((start..i, true));
+                    }
+                    result.push((i..i + 1, false));
+                    start = i + 1;
+                }
+            }
+
+            if start < range.end {
+                result.push((start..range.end, true));
+            }
+        }
+
+        result
+    }
+
+    fn join_pieces(&self, chars: &[char], pieces: Vec<Piece>) -> Vec<Piece> {
+        let mut result: Vec<Piece> = Vec::new();
+
+        for (range, is_word) in pieces {
+            match result.as_mut_slice() {
+                // Punctuation comes in pieces from the Unicode boundaries
+                [.., (last, false)] if !is_word => last.end = range.end,
+
+                // A single joiner between two words
+                [.., (word, true), (joiner, false)]
+                    if is_word && joiner.len() == 1 && self.is_joiner(chars[joiner.start]) => {
+                    word.end = range.end;
+                    result.pop();
+                }
+
+                _ => result.push((range, is_word)),
+            }
+        }
+
+        result
+    }
+
+    // Each token gets the markup of its first character, and the
+    // sources of all of them
+    pub fn tokens(&self, chars: &[StyledChar]) -> Vec<Token> {
+        let plain: Vec<char> = chars.iter().map(|(c, ..)| *c).collect();
+        let pieces = self.join_pieces(&plain, self.split_pieces(&plain));
+        let pieces = split_at_markup(chars, pieces);
+
+        pieces
+            .into_iter()
+            .map(|(range, is_word)| {
+                let text: String = plain[range.clone()].iter().collect();
+                let markup = chars[range.start].1.clone();
+                let span = chars[range].iter().fold(Span::default(), |span, (.., s)| span.union(*s));
+
+                if is_word { Token::Word(text, markup, span) } else { Token::NonWord(text, markup, span) }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::article_parser::Markup;
+
+    fn segment(segmentation: Segmentation, text: &str) -> Vec<String> {
+        let markup = Markup::default();
+        let chars: Vec<StyledChar> = text.chars().map(|c| (c, &markup, Span::default())).collect();
+
+        segmentation
+            .tokens(&chars)
+            .into_iter()
+            .map(|token| match token {
+                Token::Word(text, ..) => format!("[{}]", text),
+                Token::NonWord(text, ..) => text,
+                Token::LineBreak(_) => String::from("\n"),
+            })
+            .collect()
+    }
+
+    fn with(boundaries: WordBoundaries, join_apostrophes: bool, join_hyphens: bool) -> Segmentation {
+        Segmentation { boundaries, join_apostrophes, join_hyphens }
+    }
+
+    #[test]
+    fn letters_and_digits() {
+        assert_eq!(segment(Segmentation::default(), "Pi is 3.14, roughly."),
+            ["[Pi]", " ", "[is]", " ", "[3]", ".", "[14]", ", ", "[roughly]", "."]);
+    }
+
+    #[test]
+    fn unicode_words() {
+        let segmentation = with(WordBoundaries::Unicode, true, false);
+
+        assert_eq!(segment(segmentation, "Pi is 3.14, roughly."),
+            ["[Pi]", " ", "[is]", " ", "[3.14]", ", ", "[roughly]", "."]);
+
+        // Combining marks stay with their letter
+        assert_eq!(segment(segmentation, "cafe\u{301} noir"), ["[cafe\u{301}]", " ", "[noir]"]);
+    }
+
+    #[test]
+    fn apostrophes() {
+        for boundaries in WordBoundaries::ALL {
+            assert_eq!(segment(with(boundaries, true, false), "Don't ask O’Brien"),
+                ["[Don't]", " ", "[ask]", " ", "[O’Brien]"]);
+            assert_eq!(segment(with(boundaries, false, false), "Don't"), ["[Don]", "'", "[t]"]);
+
+            // Quotes around a word are not joined
+            assert_eq!(segment(with(boundaries, true, false), "'cat'"), ["'", "[cat]", "'"]);
+        }
+    }
+
+    #[test]
+    fn hyphens() {
+        for boundaries in WordBoundaries::ALL {
+            assert_eq!(segment(with(boundaries, true, false), "well-known"), ["[well]", "-", "[known]"]);
+            assert_eq!(segment(with(boundaries, true, true), "well-known"), ["[well-known]"]);
+
+            // Dashes are never joined
+            assert_eq!(segment(with(boundaries, true, true), "1914–1918"), ["[1914]", "–", "[1918]"]);
+            assert_eq!(segment(with(boundaries, true, true), "well--known"), ["[well]", "--", "[known]"]);
+        }
+    }
+
+    #[test]
+    fn markup_changes_in_unspaced_scripts() {
+        let plain = Markup::default();
+        let bold = Markup { bold: true, ..Markup::default() };
+        let styled = |text: &str, markup| -> Vec<StyledChar> {
+            text.chars().map(|c| (c, markup, Span::default())).collect()
+        };
+
+        let chars = [styled("แมว", &bold), styled("ดำ", &plain)].concat();
+        let tokens = Segmentation::default().tokens(&chars);
+        assert_eq!(tokens.len(), 2);
+        assert!(matches!(&tokens[0], Token::Word(text, markup, _) if text == "แมว" && markup.bold));
+
+        let chars = [styled("black", &bold), styled("cat", &plain)].concat();
+        assert_eq!(Segmentation::default().tokens(&chars).len(), 1);
+    }
+}
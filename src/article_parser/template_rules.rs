@@ -0,0 +1,224 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use super::templates::{TemplateCall, TemplateHandler, TemplateRegistry};
+
+// Templates that only pick out some of their parameters can be described
+// by rules in a JSON file instead of code. The file holds a list of rules
+// like
+//
+//   { "names": ["abbr"], "formats": ["{2} ({1})", "{2}", "{1}"] }
+//
+// A rule applies to the templates listed in "names", and/or to all
+// templates starting with "name_prefix". Instead of formats, a rule can
+// give "alias_of" to make its names redirect to another template.
+//
+// Each format may refer to numbered parameters as {1}, {2}, ..., to named
+// parameters as {title}, and to the name of the template as {#name}. The
+// first format for which all referenced parameters are given is used;
+// if there is none, the template expands to "default" (or nothing).
+// "output" is a shorthand for a single format consisting of one
+// parameter, and "prefix" and "suffix" are put around a non-empty result.
+
+pub struct TemplateRule {
+    formats: Vec<String>,
+    default: String,
+    prefix: String,
+    suffix: String,
+}
+
+enum Placeholder<'s> {
+    Numbered(usize),
+    Named(&'s str),
+    TemplateName,
+}
+
+fn placeholder(key: &str) -> Placeholder<'_> {
+    if key == "#name" {
+        Placeholder::TemplateName
+    } else if let Ok(number) = key.parse() {
+        Placeholder::Numbered(number)
+    } else {
+        Placeholder::Named(key)
+    }
+}
+
+// Returns None if a parameter the format refers to is missing or empty
+fn apply_format(format: &str, call: &TemplateCall) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = format;
+
+    while let Some(open) = rest.find('{') {
+        result.push_str(&rest[..open]);
+
+        let close = open + rest[open..].find('}')?;
+        let value = match placeholder(rest[open + 1..close].trim()) {
+            Placeholder::Numbered(number) => call.numbered(number),
+            Placeholder::Named(name) => call.named(name),
+            Placeholder::TemplateName => Some(call.name.clone()),
+        };
+
+        let value = value.filter(|v| !v.trim().is_empty())?;
+        result.push_str(value.trim());
+
+        rest = &rest[close + 1..];
+    }
+
+    result.push_str(rest);
+    Some(result)
+}
+
+impl TemplateHandler for TemplateRule {
+    fn expand(&self, call: &TemplateCall) -> String {
+        let expanded = self.formats
+            .iter()
+            .find_map(|format| apply_format(format, call));
+
+        match expanded {
+            Some(text) if !text.is_empty() => format!("{}{}{}", self.prefix, text, self.suffix),
+            _ => self.default.clone(),
+        }
+    }
+}
+
+fn get_string(rule: &Value, key: &str) -> Result<Option<String>> {
+    match &rule[key] {
+        Value::Null => Ok(None),
+        Value::String(s) => Ok(Some(s.clone())),
+        Value::Number(n) => Ok(Some(n.to_string())),
+        _ => Err(anyhow!("\"{}\" must be a string in template rule {}", key, rule)),
+    }
+}
+
+fn get_strings(rule: &Value, key: &str) -> Result<Vec<String>> {
+    match &rule[key] {
+        Value::Null => Ok(Vec::new()),
+        Value::Array(values) => {
+            values
+                .iter()
+                .map(|value| {
+                    value
+                        .as_str()
+                        .map(String::from)
+                        .ok_or(anyhow!("\"{}\" must only contain strings in template rule {}", key, rule))
+                })
+                .collect()
+        }
+        _ => Err(anyhow!("\"{}\" must be a list in template rule {}", key, rule)),
+    }
+}
+
+impl TemplateRegistry {
+    // Registers handlers for all rules in a JSON document. Rules replace
+    // handlers registered earlier for the same names.
+    pub fn load_rules(&mut self, json: &str) -> Result<()> {
+        let rules: Value = serde_json::from_str(json)?;
+        let rules = rules.as_array().ok_or(anyhow!("Template rules must be a list"))?;
+
+        for rule in rules {
+            let names = get_strings(rule, "names")?;
+            let name_prefix = get_string(rule, "name_prefix")?;
+
+            if names.is_empty() && name_prefix.is_none() {
+                return Err(anyhow!("Template rule {} applies to no templates", rule));
+            }
+
+            if let Some(target) = get_string(rule, "alias_of")? {
+                for name in &names {
+                    self.alias(name, &target);
+                }
+                continue;
+            }
+
+            let mut formats = get_strings(rule, "formats")?;
+            if let Some(output) = get_string(rule, "output")? {
+                formats.insert(0, format!("{{{}}}", output));
+            }
+
+            let make_rule = || -> Result<TemplateRule> {
+                Ok(TemplateRule {
+                    formats: formats.clone(),
+                    default: get_string(rule, "default")?.unwrap_or_default(),
+                    prefix: get_string(rule, "prefix")?.unwrap_or_default(),
+                    suffix: get_string(rule, "suffix")?.unwrap_or_default(),
+                })
+            };
+
+            if !names.is_empty() {
+                let names: Vec<&str> = names.iter().map(String::as_str).collect();
+                self.register_all(&names, make_rule()?);
+            }
+
+            if let Some(prefix) = name_prefix {
+                self.register_prefix(&prefix, make_rule()?);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load_rules_file(&mut self, path: &Path) -> Result<()> {
+        let json = fs::read_to_string(path)?;
+        self.load_rules(&json)
+            .map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::article_parser::tests;
+    use crate::article_parser::ParserConfig;
+
+    fn expand(rules: &str, wikitext: &str) -> String {
+        let mut config = ParserConfig::default();
+        config.templates.load_rules(rules).unwrap();
+
+        tests::expand(&config, wikitext)
+    }
+
+    #[test]
+    fn first_format_with_all_parameters() {
+        let rules = r#"[{ "names": ["abbr"], "formats": ["{2} ({1})", "{2}", "{1}"] }]"#;
+
+        assert_eq!(expand(rules, "{{abbr|NASA|National Aeronautics and Space Administration}}"),
+            "National Aeronautics and Space Administration (NASA)");
+        assert_eq!(expand(rules, "{{abbr||Only the second}}"), "Only the second");
+        assert_eq!(expand(rules, "{{Abbr|NASA}}"), "NASA");
+    }
+
+    #[test]
+    fn named_parameters_and_template_names() {
+        let rules = r##"[
+            { "names": ["book"], "formats": ["{title} by {author}"], "default": "a book" },
+            { "name_prefix": "flag ", "output": "#name", "prefix": "[", "suffix": "]" }
+        ]"##;
+
+        assert_eq!(expand(rules, "{{book|author=Orwell|title=Animal Farm}}"), "Animal Farm by Orwell");
+        assert_eq!(expand(rules, "{{book|title=Animal Farm}}"), "a book");
+        assert_eq!(expand(rules, "{{flag France}}"), "[flag France]");
+    }
+
+    #[test]
+    fn aliases() {
+        let rules = r#"[
+            { "names": ["lang"], "output": "2" },
+            { "names": ["ill"], "alias_of": "lang" }
+        ]"#;
+
+        assert_eq!(expand(rules, "{{ill|fr|Bonjour}}"), "Bonjour");
+    }
+
+    #[test]
+    fn invalid_rules() {
+        let mut registry = TemplateRegistry::new();
+
+        assert!(registry.load_rules("{}").is_err());
+        assert!(registry.load_rules(r#"[{ "formats": ["{1}"] }]"#).is_err());
+        assert!(registry.load_rules(r#"[{ "names": "abbr" }]"#).is_err());
+        assert!(registry.load_rules(r#"[{ "names": ["abbr"], "default": [] }]"#).is_err());
+    }
+}
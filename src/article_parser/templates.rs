@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use parse_wiki_text::{Node, Parameter};
+
+use super::{get_inline_text, ParserConfig};
+
+// A template as it appears in the wiki text, with helpers to get at the
+// text of its parameters.
+pub struct TemplateCall<'a, 'b> {
+    // The name as written in the article
+    pub name: String,
+    pub parameters: &'b Vec<Parameter<'a>>,
+    pub config: &'b ParserConfig,
+}
+
+impl<'a, 'b> TemplateCall<'a, 'b> {
+    pub fn text(&self, nodes: &Vec<Node>) -> String {
+        get_inline_text(self.config, nodes)
+    }
+
+    // Parameters are counted from zero, named ones included
+    pub fn positional(&self, index: usize) -> Option<String> {
+        self.parameters
+            .get(index)
+            .map(|param| { self.text(&param.value) })
+    }
+
+    // Numbered parameters as in the wiki text: {{{1}}} is the first
+    // parameter without a name, unless one is explicitly named "1"
+    pub fn numbered(&self, number: usize) -> Option<String> {
+        if let Some(value) = self.named(&number.to_string()) {
+            return Some(value);
+        }
+
+        self.parameters
+            .iter()
+            .filter(|param| param.name.is_none())
+            .nth(number.checked_sub(1)?)
+            .map(|param| self.text(&param.value))
+    }
+
+    pub fn named(&self, name: &str) -> Option<String> {
+        for param in self.parameters.iter() {
+            if let Some(name_nodes) = &param.name {
+                if self.text(name_nodes).trim() == name {
+                    return Some(self.text(&param.value));
+                }
+            }
+        }
+        None
+    }
+}
+
+pub trait TemplateHandler {
+    fn expand(&self, call: &TemplateCall) -> String;
+}
+
+impl<F: Fn(&TemplateCall) -> String> TemplateHandler for F {
+    fn expand(&self, call: &TemplateCall) -> String {
+        self(call)
+    }
+}
+
+// Maps template names to the handlers that turn them into text.
+// Templates without a handler are dropped from the article.
+pub struct TemplateRegistry {
+    handlers: HashMap<String, Rc<dyn TemplateHandler>>,
+
+    // For families of templates such as "lang-de", "lang-fr", ...
+    prefix_handlers: Vec<(String, Rc<dyn TemplateHandler>)>,
+
+    // Redirects between template names, such as "cvt" to "convert"
+    aliases: HashMap<String, String>,
+}
+
+// Template names are case-insensitive and treat underscores like spaces
+fn normalize_name(name: &str) -> String {
+    let name = name.trim().to_lowercase().replace('_', " ");
+
+    match name.strip_prefix("template:") {
+        Some(stripped) => String::from(stripped.trim()),
+        None => name,
+    }
+}
+
+impl TemplateRegistry {
+    pub fn new() -> Self {
+        TemplateRegistry {
+            handlers: HashMap::new(),
+            prefix_handlers: Vec::new(),
+            aliases: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, name: &str, handler: impl TemplateHandler + 'static) {
+        self.handlers.insert(normalize_name(name), Rc::new(handler));
+    }
+
+    // Registers the same handler for several template names
+    pub fn register_all(&mut self, names: &[&str], handler: impl TemplateHandler + 'static) {
+        let handler: Rc<dyn TemplateHandler> = Rc::new(handler);
+
+        for name in names {
+            self.handlers.insert(normalize_name(name), handler.clone());
+        }
+    }
+
+    pub fn register_prefix(&mut self, prefix: &str, handler: impl TemplateHandler + 'static) {
+        self.prefix_handlers.push((normalize_name(prefix), Rc::new(handler)));
+    }
+
+    pub fn alias(&mut self, alias: &str, target: &str) {
+        self.aliases.insert(normalize_name(alias), normalize_name(target));
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&dyn TemplateHandler> {
+        let mut name = normalize_name(name);
+
+        // Follow redirects, but do not get caught in loops
+        for _ in 0..10 {
+            match self.aliases.get(&name) {
+                Some(target) => name = target.clone(),
+                None => break,
+            }
+        }
+
+        if let Some(handler) = self.handlers.get(&name) {
+            return Some(handler.as_ref());
+        }
+
+        // Later registrations take precedence
+        self.prefix_handlers
+            .iter()
+            .rev()
+            .find(|(prefix, _)| name.starts_with(prefix.as_str()))
+            .map(|(_, handler)| handler.as_ref())
+    }
+}
+
+const BUILTIN_RULES: &str = include_str!("../assets/templates.json");
+
+impl Default for TemplateRegistry {
+    fn default() -> Self {
+        let mut registry = TemplateRegistry::new();
+
+        registry.load_rules(BUILTIN_RULES).expect("Built-in template rules must be valid");
+
+        registry.register("blockquote", blockquote);
+        registry.register("convert", convert);
+
+        super::date_templates::register(&mut registry);
+
+        registry
+    }
+}
+
+fn blockquote(call: &TemplateCall) -> String {
+    let quote = call.positional(0);
+    let source = call.positional(1);
+
+    let mut result = String::new();
+
+    if let Some(q) = quote {
+        result.push('“');
+        result.push_str(&q);
+        result.push('”');
+
+        if let Some(s) = source {
+            result.push_str(" – ");
+            result.push_str(&s);
+        }
+    }
+
+    result
+}
+
+fn convert(call: &TemplateCall) -> String {
+    let number = call.positional(0).unwrap_or(String::from("???"));
+    let unit = call.positional(1);
+
+    if let Some(ref u) = unit {
+        if u == "to" {
+            // We have a range of values, given as {{convert|min|to|max|unit|...}}
+
+            let maximum = call.positional(2).unwrap_or(String::from("???"));
+
+            return match call.positional(3) {
+                None => format!("{} to {}", number, maximum),
+                Some(u) => format!("{} to {} {}", number, maximum, u),
+            };
+        }
+    }
+
+    match unit {
+        None => number,
+        Some(u) => format!("{} {}", number, u),
+    }
+}
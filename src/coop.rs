@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::mpsc::Receiver;
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+use crate::game::Game;
+use crate::multiplayer;
+use crate::net;
+use crate::net::{Connection, Event, Listener};
+
+// Cooperative play: the host owns the game, clients send their guesses
+// to the host, and the host passes the article and every guess on to
+// all clients.
+//
+// Both sides compare the game against what has already been shared, so
+// the frontends can keep calling Game::guess and Game::load_article as
+// usual and only need to poll the session regularly.
+
+pub enum Session {
+    Host(Host),
+    Client(Client),
+}
+
+impl Session {
+    // Returns whether the game was changed by other players.
+    pub fn poll(&mut self, game: &mut Game) -> Result<bool> {
+        match self {
+            Session::Host(host) => Ok(host.poll(game)),
+            Session::Client(client) => client.poll(game),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Session::Host(host) => {
+                format!("Hosting on port {} ({} players joined)", host.listener.port, host.clients.len())
+            }
+
+            Session::Client(client) => {
+                format!("Joined game at {}", client.address)
+            }
+        }
+    }
+
+    pub fn is_client(&self) -> bool {
+        matches!(self, Session::Client(_))
+    }
+}
+
+fn guess_message(player: &str, word: &str) -> Value {
+    json!({
+        "type": "guess",
+        "player": player,
+        "word": word,
+    })
+}
+
+pub struct Host {
+    listener: Listener,
+    clients: BTreeMap<usize, Connection>,
+
+    shared_title: Option<String>,
+    shared_guesses: BTreeSet<String>,
+}
+
+impl Host {
+    pub fn start(port: u16) -> Result<Host> {
+        Ok(Host {
+            listener: net::listen(port)?,
+            clients: BTreeMap::new(),
+            shared_title: None,
+            shared_guesses: BTreeSet::new(),
+        })
+    }
+
+    fn send_shared_state(&self, connection: &mut Connection, game: &Game) -> Result<()> {
+        if self.shared_title.is_some() {
+            connection.send(&multiplayer::article_message(game))?;
+
+            for guess in &self.shared_guesses {
+                let player = game.guessed_by.get(guess).map(String::as_str).unwrap_or("");
+                connection.send(&guess_message(player, guess))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn poll(&mut self, game: &mut Game) -> bool {
+        let mut changed = false;
+
+        while let Ok(event) = self.listener.events.try_recv() {
+            match event {
+                Event::Connected(id, mut connection) => {
+                    if self.send_shared_state(&mut connection, game).is_ok() {
+                        self.clients.insert(id, connection);
+                    }
+                }
+
+                Event::Message(_id, message) => {
+                    if message["type"] == "guess" {
+                        if let (Some(player), Some(word)) = (message["player"].as_str(), message["word"].as_str()) {
+                            game.guess_as(player, word);
+                            changed = true;
+                        }
+                    }
+                }
+
+                Event::Disconnected(id) => {
+                    self.clients.remove(&id);
+                }
+            }
+        }
+
+        let title = game.title();
+        if title.is_some() && title != self.shared_title {
+            self.shared_title = title;
+            self.shared_guesses.clear();
+            net::broadcast(&mut self.clients, &multiplayer::article_message(game));
+        }
+
+        let new_guesses: Vec<String> = game.guesses.difference(&self.shared_guesses).cloned().collect();
+        for guess in new_guesses {
+            let player = game.guessed_by.get(&guess).cloned().unwrap_or_default();
+            net::broadcast(&mut self.clients, &guess_message(&player, &guess));
+            self.shared_guesses.insert(guess);
+        }
+
+        changed
+    }
+}
+
+pub struct Client {
+    address: String,
+    connection: Connection,
+    events: Receiver<Event>,
+
+    shared_guesses: BTreeSet<String>,
+}
+
+impl Client {
+    pub fn connect(address: &str) -> Result<Client> {
+        let (connection, events) = net::connect(address)?;
+
+        Ok(Client {
+            address: String::from(address),
+            connection,
+            events,
+            shared_guesses: BTreeSet::new(),
+        })
+    }
+
+    pub fn poll(&mut self, game: &mut Game) -> Result<bool> {
+        let mut changed = false;
+
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Event::Message(_id, message) => {
+                    match message["type"].as_str() {
+                        Some("article") => {
+                            multiplayer::load_article_message(game, &message);
+                            self.shared_guesses.clear();
+                            changed = true;
+                        }
+
+                        Some("guess") => {
+                            if let (Some(player), Some(word)) = (message["player"].as_str(), message["word"].as_str()) {
+                                game.guess_as(player, word);
+                                self.shared_guesses.insert(String::from(word));
+                                changed = true;
+                            }
+                        }
+
+                        _ => { }
+                    }
+                }
+
+                Event::Disconnected(_) => {
+                    return Err(anyhow!("Lost connection to host at {}", self.address));
+                }
+
+                Event::Connected(..) => { }
+            }
+        }
+
+        let new_guesses: Vec<String> = game.guesses.difference(&self.shared_guesses).cloned().collect();
+        for guess in new_guesses {
+            self.connection.send(&guess_message(&game.player_name, &guess))?;
+            self.shared_guesses.insert(guess);
+        }
+
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::article_parser::SourceFormat;
+    use crate::net::tests::poll_until;
+
+    #[test]
+    fn host_join_guess_leave_and_host_again() {
+        let mut host = Host::start(0).unwrap();
+        let port = host.listener.port;
+
+        let mut host_game = Game::new();
+        host_game.parser_config.collect_notes = true;
+        host_game.load_source("en", "Test", SourceFormat::Wikitext, "A cat is an animal.<ref>Cats are small.</ref>");
+        host.poll(&mut host_game);
+
+        let mut client = Client::connect(&format!("127.0.0.1:{}", port)).unwrap();
+        let mut client_game = Game::new();
+
+        assert!(poll_until(|| {
+            host.poll(&mut host_game);
+            client.poll(&mut client_game).unwrap();
+            client_game.title().is_some()
+        }));
+        assert_eq!(host.clients.len(), 1);
+
+        // The client plays the article as parsed by the host, notes and all
+        assert_eq!(client_game.wiki_article, host_game.wiki_article);
+
+        client_game.guess("cat");
+        client.poll(&mut client_game).unwrap();
+        assert!(poll_until(|| {
+            host.poll(&mut host_game);
+            host_game.guesses.contains("cat")
+        }));
+
+        // The host notices when a client leaves
+        drop(client);
+        assert!(poll_until(|| {
+            host.poll(&mut host_game);
+            host.clients.is_empty()
+        }));
+
+        // and the port can be used again once the host stops
+        drop(host);
+        Host::start(port).unwrap();
+    }
+}
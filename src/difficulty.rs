@@ -0,0 +1,153 @@
+use crate::article_parser::{Token, WikiArticle};
+use crate::word_frequency::WordFrequency;
+
+// Rough estimate of how hard it is to guess the title of an article,
+// from 0.0 (trivial) to 1.0 (hopeless).
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    fn score_range(&self) -> (f32, f32) {
+        match self {
+            Difficulty::Easy => (0.0, 0.3),
+            Difficulty::Medium => (0.3, 0.45),
+            Difficulty::Hard => (0.45, 1.0),
+        }
+    }
+
+    pub fn of_score(score: f32) -> Difficulty {
+        if score < Difficulty::Easy.score_range().1 {
+            Difficulty::Easy
+        } else if score < Difficulty::Medium.score_range().1 {
+            Difficulty::Medium
+        } else {
+            Difficulty::Hard
+        }
+    }
+
+    // How far a score is outside of this difficulty band
+    pub fn distance(&self, score: f32) -> f32 {
+        let (min, max) = self.score_range();
+        f32::max(0.0, f32::max(min - score, score - max))
+    }
+}
+
+fn words<'a>(tokens: impl Iterator<Item = &'a Token>) -> Vec<String> {
+    tokens
+        .filter_map(|token| match token {
+            Token::Word(w, ..) => Some(w.to_lowercase()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Between 0.0 for the most frequent word and 1.0 for words not in the list
+fn rarity(word: &str, frequency: &WordFrequency) -> f32 {
+    match frequency.rank(word) {
+        Some(rank) => rank as f32 / frequency.len() as f32,
+        None => 1.0,
+    }
+}
+
+pub fn estimate(article: &WikiArticle, frequency: &WordFrequency) -> f32 {
+    let title_words = words(article.title.iter());
+    let body_words = words(article.content.iter().flat_map(|section| section.tokens()));
+
+    if title_words.is_empty() || body_words.is_empty() {
+        return 1.0;
+    }
+
+    // Rare words in the title are hard to come up with, unless the body
+    // mentions them so often that the blanks around them give them away.
+    // Most titles are names missing from any word list, so how often
+    // they are mentioned is what tells those apart.
+    let title_rarity = title_words
+        .iter()
+        .map(|title_word| {
+            let mentions = body_words.iter().filter(|word| *word == title_word).count() as f32;
+            rarity(title_word, frequency) / (1.0 + mentions / 3.0)
+        })
+        .sum::<f32>() / title_words.len() as f32;
+
+    // Short articles give few clues
+    let shortness = 1.0 / (1.0 + body_words.len() as f32 / 500.0);
+
+    // Articles full of uncommon words take many guesses to uncover
+    let vocabulary_rarity = body_words
+        .iter()
+        .filter(|word| frequency.rank(word).is_none())
+        .count() as f32 / body_words.len() as f32;
+
+    0.5 * title_rarity + 0.2 * shortness + 0.3 * vocabulary_rarity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::article_parser;
+    use crate::article_parser::ParserConfig;
+
+    // An article about a name missing from the word list, mentioned the
+    // given number of times among sentences with about as many uncommon
+    // words as real articles have
+    fn name_article(mentions: usize, sentences: usize) -> WikiArticle {
+        let mut content = String::new();
+        for i in 0..sentences {
+            if i < mentions {
+                content.push_str("Zanzibar ");
+            }
+            content.push_str("was one of the first coastal towns with spice markets, coral reefs and mangrove lagoons. ");
+        }
+
+        article_parser::parse(&ParserConfig::default(), "en", "Zanzibar", &content).0
+    }
+
+    #[test]
+    fn names_mentioned_often_are_easy() {
+        let frequency = WordFrequency::builtin();
+
+        let score = estimate(&name_article(60, 100), &frequency);
+        assert!(Difficulty::of_score(score) == Difficulty::Easy, "score {}", score);
+    }
+
+    #[test]
+    fn names_mentioned_sometimes_are_medium() {
+        let frequency = WordFrequency::builtin();
+
+        let score = estimate(&name_article(8, 50), &frequency);
+        assert!(Difficulty::of_score(score) == Difficulty::Medium, "score {}", score);
+    }
+
+    #[test]
+    fn names_hardly_mentioned_are_hard() {
+        let frequency = WordFrequency::builtin();
+
+        let score = estimate(&name_article(1, 10), &frequency);
+        assert!(Difficulty::of_score(score) == Difficulty::Hard, "score {}", score);
+    }
+
+    #[test]
+    fn common_titles_are_easier_than_names() {
+        let frequency = WordFrequency::builtin();
+        let content = "The city was one of the first new cities in the national part of the united states. ".repeat(5);
+
+        let common = article_parser::parse(&ParserConfig::default(), "en", "City", &content).0;
+        let name = article_parser::parse(&ParserConfig::default(), "en", "Zanzibar", &content).0;
+        assert!(estimate(&common, &frequency) < estimate(&name, &frequency));
+    }
+}
@@ -1,13 +1,18 @@
 #![allow(non_snake_case)]
 
+use std::path::Path;
+use std::time::Duration;
+
 use anyhow::Result;
 
 use dioxus::prelude::*;
 
 use keyboard_types::Key;
 
-use crate::article_parser::{Token, Section};
+use crate::article_parser::{ParseWarning, Token, Section, SectionLimit, SourceFormat, WordBoundaries};
+use crate::difficulty::Difficulty;
 use crate::game::{Game, TokenTreatment};
+use crate::multiplayer;
 
 pub fn launch() {
     dioxus_desktop::launch(app);
@@ -32,25 +37,95 @@ fn HeadingN<'a>(cx: Scope<'a, HeadingNProps<'a>>) -> Element {
     }
 }
 
+// Handed down to the tokens, so that links in a finished game can be
+// followed from anywhere in the article
+#[derive(Clone)]
+struct LinkFollower {
+    load_result: UseState<Result<()>>,
+    multiplayer_session: UseRef<Option<multiplayer::Session>>,
+}
+
+impl LinkFollower {
+    fn is_enabled(&self) -> bool {
+        multiplayer::Session::can_choose_articles(self.multiplayer_session.read().as_ref())
+    }
+
+    fn follow(&self, game: &UseSharedState<Game>, target: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let language = game.read().language.clone();
+        let res = game.write().load_article(&language, target);
+        self.load_result.set(res);
+    }
+}
+
 #[inline_props]
 fn Token(cx: Scope, token: Token) -> Element {
+    if let Token::LineBreak(_) = token {
+        return cx.render(rsx!( br { } ));
+    }
+
     let game = use_shared_state::<Game>(cx).unwrap();
+    let link_follower = use_context::<LinkFollower>(cx).filter(|follower| follower.is_enabled());
+
+    let font_weight = if token.markup().bold { "bold" } else { "normal" };
+    let font_style = if token.markup().italic { "italic" } else { "normal" };
 
-    match game.read().get_token_treatment(token) {
+    let treatment = game.read().get_token_treatment(token);
+    let link_target = game.read().link_target(token).map(String::from);
+
+    if let (Some(target), Some(link_follower)) = (link_target, link_follower) {
+        let background_color = match treatment {
+            TokenTreatment::Highlight => "cyan",
+            _ => "transparent",
+        };
+
+        return cx.render(rsx!(
+            a {
+                class: "article-link",
+                background_color: background_color,
+                font_weight: font_weight,
+                font_style: font_style,
+                onclick: move |_| link_follower.follow(game, &target),
+
+                token.get_str()
+            }
+        ));
+    }
+
+    match treatment {
         TokenTreatment::Blank => {
             let dashes: Vec<&str> = std::iter::repeat("_").take(token.char_count()).collect();
             let dashes = dashes.concat();
-            cx.render(rsx!(dashes))
+            cx.render(rsx!(
+                span {
+                    font_weight: font_weight,
+                    font_style: font_style,
+
+                    dashes
+                }
+            ))
         }
 
         TokenTreatment::Show => {
-            cx.render(rsx!(token.get_str()))
+            cx.render(rsx!(
+                span {
+                    font_weight: font_weight,
+                    font_style: font_style,
+
+                    token.get_str()
+                }
+            ))
         }
 
         TokenTreatment::Highlight => {
             cx.render(rsx!(
                 span {
                     background_color: "cyan",
+                    font_weight: font_weight,
+                    font_style: font_style,
 
                     token.get_str(),
                 }
@@ -70,6 +145,52 @@ fn Title(cx: Scope, tokens: Vec<Token>) -> Element {
     })
 }
 
+#[inline_props]
+fn PageWarning(cx: Scope) -> Element {
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let warning = game.read().page_kind.warning().map(String::from);
+    let fallback = game.read().random_fallback_warning();
+
+    match warning.or(fallback) {
+        Some(warning) => cx.render(rsx!( div { id: "page-warning", warning } )),
+        None => cx.render(rsx!( div { } )),
+    }
+}
+
+#[inline_props]
+fn ParseWarnings(cx: Scope) -> Element {
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let game = game.read();
+    let warnings: Vec<(ParseWarning, String)> = game.parse_warnings
+        .iter()
+        .map(|warning| (warning.clone(), String::from(game.parse_warning_snippet(warning))))
+        .collect();
+
+    if warnings.is_empty() {
+        return cx.render(rsx!( div { } ));
+    }
+
+    let summary = format!("Parsed with {} warnings", warnings.len());
+
+    cx.render(rsx!(
+        details {
+            id: "parse-warnings",
+
+            summary { summary }
+
+            table {
+                for (warning, snippet) in warnings {
+                    tr {
+                        td { format!("{}–{}", warning.start, warning.end) },
+                        td { warning.message.clone() },
+                        td { code { snippet } }
+                    }
+                }
+            }
+        }
+    ))
+}
+
 #[inline_props]
 fn ArticleSection(cx: Scope, section: Section) -> Element {
     match section {
@@ -116,6 +237,121 @@ fn ArticleSection(cx: Scope, section: Section) -> Element {
                 }
             ))
         }
+        Section::Infobox(rows) => {
+            cx.render(rsx!(
+                table {
+                    class: "infobox",
+
+                    for (label, value) in rows {
+                        tr {
+                            th {
+                                for token in label {
+                                    cx.render(rsx!(Token { token: token.clone() }))
+                                }
+                            }
+                            td {
+                                for token in value {
+                                    cx.render(rsx!(Token { token: token.clone() }))
+                                }
+                            }
+                        }
+                    }
+                }
+            ))
+        }
+        Section::DefinitionList(entries) => {
+            cx.render(rsx!(
+                dl {
+                    for (term, details) in entries {
+                        if !term.is_empty() {
+                            rsx!(
+                                dt {
+                                    for token in term {
+                                        cx.render(rsx!(Token { token: token.clone() }))
+                                    }
+                                }
+                            )
+                        }
+
+                        for detail in details {
+                            rsx!(
+                                dd {
+                                    ArticleSections { sections: detail.clone() }
+                                }
+                            )
+                        }
+                    }
+                }
+            ))
+        }
+        Section::Quote(sections) => {
+            cx.render(rsx!(
+                blockquote {
+                    ArticleSections { sections: sections.clone() }
+                }
+            ))
+        }
+        Section::Preformatted(tokens) => {
+            cx.render(rsx!(
+                pre {
+                    for token in tokens {
+                        cx.render(rsx!(Token { token: token.clone() }))
+                    }
+                }
+            ))
+        }
+        Section::Figure(tokens) => {
+            cx.render(rsx!(
+                figure {
+                    figcaption {
+                        for token in tokens {
+                            cx.render(rsx!(Token { token: token.clone() }))
+                        }
+                    }
+                }
+            ))
+        }
+        Section::Table(caption, rows) => {
+            cx.render(rsx!(
+                table {
+                    class: "article-table",
+
+                    if let Some(caption) = caption {
+                        rsx!(
+                            caption {
+                                for token in caption {
+                                    cx.render(rsx!(Token { token: token.clone() }))
+                                }
+                            }
+                        )
+                    }
+
+                    for row in rows {
+                        tr {
+                            for cell in row {
+                                if cell.header {
+                                    rsx!(
+                                        th {
+                                            for token in &cell.tokens {
+                                                cx.render(rsx!(Token { token: token.clone() }))
+                                            }
+                                        }
+                                    )
+                                } else {
+                                    rsx!(
+                                        td {
+                                            for token in &cell.tokens {
+                                                cx.render(rsx!(Token { token: token.clone() }))
+                                            }
+                                        }
+                                    )
+                                }
+                            }
+                        }
+                    }
+                }
+            ))
+        }
     }
 }
 
@@ -134,15 +370,16 @@ fn ArticleSections(cx: Scope, sections: Vec<Section>) -> Element {
 fn GuessesTable(cx: Scope) -> Element {
     let game = use_shared_state::<Game>(cx).unwrap();
 
-    let guesses_list: Vec<(usize, String)> = game.read().guesses.iter().map(|guess| {
+    let guesses_list: Vec<(usize, String, String)> = game.read().guesses.iter().map(|guess| {
 	let guess = guess.clone();
-	(game.read().count_word_in_article(&guess).unwrap(), guess)
+	let player = game.read().guessed_by.get(&guess).cloned().unwrap_or_default();
+	(game.read().count_word_in_article(&guess).unwrap(), guess, player)
     }).collect();
 
     cx.render(rsx!( table {
 	id: "guesses-table",
 
-	for (i, (count, guess)) in guesses_list.iter().enumerate() {
+	for (i, (count, guess, player)) in guesses_list.iter().enumerate() {
 	    tr {
 		onclick: move |_| {
 		    // TODO This is a rather roundabout way, but we
@@ -166,12 +403,43 @@ fn GuessesTable(cx: Scope) -> Element {
 		td {
 		    class: "guesses-word",
 		    guess.to_string()
+		},
+
+		td {
+		    class: "guesses-player",
+		    player.to_string()
 		}
 	    }
 	}
     }))
 }
 
+#[inline_props]
+fn Scoreboard<'a>(cx: Scope<'a>, multiplayer_session: &'a UseRef<Option<multiplayer::Session>>) -> Element {
+    let session = multiplayer_session.read();
+    let Some((rivals, winner)) = session.as_ref().and_then(|s| s.scoreboard()) else {
+	return cx.render(rsx!( div { } ));
+    };
+
+    cx.render(rsx!(
+	if let Some(w) = winner {
+	    rsx!( p { "{w} won the race!" } )
+	}
+
+	table {
+	    id: "scoreboard",
+
+	    for rival in rivals {
+		tr {
+		    td { rival.player.clone() },
+		    td { class: "guesses-count", "{rival.guesses}" },
+		    td { class: "guesses-count", format!("{:.0}%", rival.progress * 100.0) }
+		}
+	    }
+	}
+    ))
+}
+
 fn load_article(game: &UseSharedState<Game>,
 		language_tag: &UseState<String>,
 		article_title: &UseState<String>,
@@ -187,6 +455,112 @@ fn load_article(game: &UseSharedState<Game>,
     load_result.set(res);
 }
 
+fn start_multiplayer_session(multiplayer_session: &UseRef<Option<multiplayer::Session>>,
+		      multiplayer_error: &UseState<Option<String>>,
+		      session: Result<multiplayer::Session>) {
+    match session {
+	Ok(s) => {
+	    multiplayer_session.set(Some(s));
+	    multiplayer_error.set(None);
+	}
+
+	Err(e) => {
+	    multiplayer_error.set(Some(format!("{}", e)));
+	}
+    }
+}
+
+#[inline_props]
+fn MultiplayerBar<'a>(cx: Scope<'a>,
+	       multiplayer_session: &'a UseRef<Option<multiplayer::Session>>,
+	       multiplayer_error: &'a UseState<Option<String>>) -> Element {
+    let game = use_shared_state::<Game>(cx).unwrap();
+    let host_address = use_state(cx, || "localhost".to_string());
+    let mode = use_state(cx, || multiplayer::Mode::Coop);
+
+    let status = multiplayer_session.read().as_ref().map(|session| session.description());
+
+    cx.render(rsx! (
+	span { class: "toolbar-spacer" }
+
+	span { class: "toolbar-item", "Player:" }
+
+	input {
+	    class: "toolbar-item",
+	    style: "width: 8em",
+
+	    value: "{game.read().player_name}",
+	    oninput: move |evt| game.write().player_name = evt.value.clone(),
+	}
+
+	if let Some(description) = status {
+	    rsx!(
+		span { class: "toolbar-item", "{description}" }
+
+		button {
+		    class: "toolbar-item",
+		    onclick: move |_| multiplayer_session.set(None),
+
+		    "Leave"
+		}
+	    )
+	} else {
+	    rsx!(
+		select {
+		    class: "toolbar-item",
+		    onchange: move |evt| {
+			if evt.value == multiplayer::Mode::Race.name() {
+			    mode.set(multiplayer::Mode::Race);
+			} else {
+			    mode.set(multiplayer::Mode::Coop);
+			}
+		    },
+
+		    for m in [multiplayer::Mode::Coop, multiplayer::Mode::Race] {
+			rsx!( option {
+			    value: m.name(),
+			    selected: m == *mode.get(),
+			    m.name()
+			})
+		    }
+		}
+
+		button {
+		    class: "toolbar-item",
+		    onclick: move |_| {
+			let session = multiplayer::Session::host(*mode.get());
+			start_multiplayer_session(multiplayer_session, multiplayer_error, session);
+		    },
+
+		    "Host"
+		}
+
+		span { class: "toolbar-item", "Host address:" }
+
+		input {
+		    class: "toolbar-item",
+		    value: "{host_address}",
+		    oninput: move |evt| host_address.set(evt.value.clone()),
+		}
+
+		button {
+		    class: "toolbar-item",
+		    onclick: move |_| {
+			let session = multiplayer::Session::join(*mode.get(), host_address.trim());
+			start_multiplayer_session(multiplayer_session, multiplayer_error, session);
+		    },
+
+		    "Join"
+		}
+	    )
+	}
+
+	if let Some(e) = multiplayer_error.get() {
+	    rsx!( span { class: "toolbar-item", "Error: {e}" } )
+	}
+    ))
+}
+
 fn app(cx: Scope) -> Element {
     use_shared_state_provider(cx, || Game::new());
     let game = use_shared_state::<Game>(cx).unwrap();
@@ -194,10 +568,52 @@ fn app(cx: Scope) -> Element {
     let language_tag = use_state(cx, || "en".to_string());
     let article_title = use_state(cx, || "".to_string());
 
-    let load_result = use_state::<Result<()>>(cx, || Ok(()));
+    let load_result = use_state::<Result<()>>(cx, || game.write().load_template_rules_from_env());
 
     let next_guess = use_state(cx, || "".to_string());
 
+    let random_difficulty = use_state::<Option<Difficulty>>(cx, || None);
+
+    let multiplayer_session = use_ref::<Option<multiplayer::Session>>(cx, || None);
+    let multiplayer_error = use_state::<Option<String>>(cx, || None);
+
+    // Exchange guesses with the other players in the background
+    use_future(cx, (), |_| {
+	let game = game.clone();
+	let multiplayer_session = multiplayer_session.clone();
+	let multiplayer_error = multiplayer_error.clone();
+
+	async move {
+	    loop {
+		tokio::time::sleep(Duration::from_millis(100)).await;
+
+		let polled = multiplayer_session.write_silent().as_mut().map(|session| {
+		    session.poll(&mut game.write_silent())
+		});
+
+		match polled {
+		    Some(Ok(true)) => {
+			game.notify_consumers();
+		    }
+
+		    Some(Err(e)) => {
+			multiplayer_session.set(None);
+			multiplayer_error.set(Some(format!("{}", e)));
+		    }
+
+		    _ => { }
+		}
+	    }
+	}
+    });
+
+    use_context_provider(cx, || LinkFollower {
+        load_result: load_result.clone(),
+        multiplayer_session: multiplayer_session.clone(),
+    });
+
+    let can_choose_articles = multiplayer::Session::can_choose_articles(multiplayer_session.read().as_ref());
+
     cx.render(rsx! (
         style { include_str!("assets/dioxus.css") },
 
@@ -207,51 +623,203 @@ fn app(cx: Scope) -> Element {
             div {
                 id: "top-bar",
 
-                span { class: "toolbar-item", "Language tag:" }
+                if can_choose_articles {
+                    rsx!(
+                    span { class: "toolbar-item", "Language tag:" }
 
-                input {
-                    class: "toolbar-item",
-                    style: "width: 5em",
+                    input {
+                        class: "toolbar-item",
+                        style: "width: 5em",
 
-                    value: "{language_tag}",
-                    oninput: move |evt| language_tag.set(evt.value.clone()),
-                }
+                        value: "{language_tag}",
+                        oninput: move |evt| language_tag.set(evt.value.clone()),
+                    }
 
-                span { class: "toolbar-item", "Title:" }
+                    span { class: "toolbar-item", "Title:" }
 
-                input {
-                    class: "toolbar-item",
-                    value: "{article_title}",
+                    input {
+                        class: "toolbar-item",
+                        value: "{article_title}",
 
-                    oninput: move |evt| article_title.set(evt.value.clone()),
+                        oninput: move |evt| article_title.set(evt.value.clone()),
 
-		    onkeypress: move |evt| {
-			if evt.key() == Key::Enter {
-			    load_article(game, language_tag, article_title, load_result);
-			}
-		    },
-                }
+                        onkeypress: move |evt| {
+                            if evt.key() == Key::Enter {
+                                load_article(game, language_tag, article_title, load_result);
+                            }
+                        },
+                    }
 
-                button {
-                    class: "toolbar-item",
-                    onclick: move |_| {
-			load_article(game, language_tag, article_title, load_result);
-                    },
+                    button {
+                        class: "toolbar-item",
+                        onclick: move |_| {
+                            load_article(game, language_tag, article_title, load_result);
+                        },
 
-                    "load article"
-                }
+                        "load article"
+                    }
+
+                    label {
+                        class: "toolbar-item",
+
+                        "Open file: "
+
+                        input {
+                            r#type: "file",
+                            accept: ".md,.markdown,.txt,.html,.htm,.wiki",
+                            onchange: move |evt| {
+                                // The desktop renderer hands over full paths
+                                let path = evt.files.as_ref().and_then(|files| files.files().into_iter().next());
+
+                                if let Some(path) = path {
+                                    let res = game.write().load_file(language_tag.get(), Path::new(&path));
+                                    load_result.set(res);
+                                }
+                            },
+                        }
+                    }
+
+                    span { class: "toolbar-spacer" }
+
+                    button {
+                        class: "toolbar-item",
+                        onclick: move |_| {
+                            let res = game.write().load_random_article(*random_difficulty.get());
+                            load_result.set(res);
+                        },
 
-                span { class: "toolbar-spacer" }
+                        "Random article"
+                    }
 
-                button {
-                    class: "toolbar-item",
-                    onclick: move |_| {
-                        let res = game.write().load_random_article();
-                        load_result.set(res);
-                    },
+                    select {
+                        class: "toolbar-item",
+                        onchange: move |evt| {
+                            let difficulty = Difficulty::ALL.into_iter().find(|d| d.name() == evt.value);
+                            random_difficulty.set(difficulty);
+                        },
+
+                        option { value: "any", "any difficulty" }
+
+                        for difficulty in Difficulty::ALL {
+                            rsx!( option {
+                                value: difficulty.name(),
+                                selected: Some(difficulty) == *random_difficulty.get(),
+                                difficulty.name()
+                            })
+                        }
+                    }
 
-                    "Random article"
+                    // These take effect when the next article is loaded
+                    select {
+                        class: "toolbar-item",
+                        onchange: move |evt| {
+                            if let Some(format) = SourceFormat::from_name(&evt.value) {
+                                game.write().download_format = format;
+                            }
+                        },
+
+                        for format in SourceFormat::DOWNLOADABLE {
+                            rsx!( option {
+                                value: format.name(),
+                                selected: format == game.read().download_format,
+                                format.name()
+                            })
+                        }
+                    }
+
+                    select {
+                        class: "toolbar-item",
+                        onchange: move |evt| {
+                            if let Some(limit) = SectionLimit::CHOICES.into_iter().find(|l| l.name() == evt.value) {
+                                game.write().parser_config.section_limit = limit;
+                            }
+                        },
+
+                        for limit in SectionLimit::CHOICES {
+                            rsx!( option {
+                                value: "{limit.name()}",
+                                selected: limit == game.read().parser_config.section_limit,
+                                limit.name()
+                            })
+                        }
+                    }
+
+                    label {
+                        class: "toolbar-item",
+
+                        input {
+                            r#type: "checkbox",
+                            checked: game.read().parser_config.keep_appendices,
+                            onchange: move |evt| {
+                                game.write().parser_config.keep_appendices = evt.value == "true";
+                            },
+                        }
+
+                        "appendices"
+                    }
+
+                    label {
+                        class: "toolbar-item",
+
+                        input {
+                            r#type: "checkbox",
+                            checked: game.read().parser_config.collect_notes,
+                            onchange: move |evt| {
+                                game.write().parser_config.collect_notes = evt.value == "true";
+                            },
+                        }
+
+                        "notes"
+                    }
+
+                    select {
+                        class: "toolbar-item",
+                        onchange: move |evt| {
+                            if let Some(boundaries) = WordBoundaries::ALL.into_iter().find(|b| b.name() == evt.value) {
+                                game.write().parser_config.segmentation.boundaries = boundaries;
+                            }
+                        },
+
+                        for boundaries in WordBoundaries::ALL {
+                            rsx!( option {
+                                value: boundaries.name(),
+                                selected: boundaries == game.read().parser_config.segmentation.boundaries,
+                                boundaries.name()
+                            })
+                        }
+                    }
+
+                    label {
+                        class: "toolbar-item",
+
+                        input {
+                            r#type: "checkbox",
+                            checked: game.read().parser_config.segmentation.join_apostrophes,
+                            onchange: move |evt| {
+                                game.write().parser_config.segmentation.join_apostrophes = evt.value == "true";
+                            },
+                        }
+
+                        "join apostrophes"
+                    }
+
+                    label {
+                        class: "toolbar-item",
+
+                        input {
+                            r#type: "checkbox",
+                            checked: game.read().parser_config.segmentation.join_hyphens,
+                            onchange: move |evt| {
+                                game.write().parser_config.segmentation.join_hyphens = evt.value == "true";
+                            },
+                        }
+
+                        "join hyphens"
+                    }
+                    )
                 }
+
+                MultiplayerBar { multiplayer_session: multiplayer_session, multiplayer_error: multiplayer_error }
             }
 
             div {
@@ -261,6 +829,10 @@ fn app(cx: Scope) -> Element {
                     rsx!( div {
                         id: "article-body",
 
+                        PageWarning { },
+
+                        ParseWarnings { },
+
                         Title { tokens: wiki_article.title.clone() },
 
                         ArticleSections { sections: wiki_article.content.clone() }
@@ -301,6 +873,8 @@ fn app(cx: Scope) -> Element {
             div {
                 id: "guesses-table-area",
 
+		Scoreboard { multiplayer_session: multiplayer_session },
+
 		GuessesTable { },
             }
         }
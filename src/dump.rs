@@ -0,0 +1,203 @@
+use std::fs;
+use std::io::{stdout, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::article_parser::{self, ParseWarning, ParserConfig, SectionLimit, SourceFormat, WikiArticle};
+use crate::wikipedia_api;
+
+// Writes the parsed structure of an article, so other tools can use the
+// parser without linking against it
+
+#[derive(Serialize)]
+struct Dump<'a> {
+    language: &'a str,
+    format: &'a str,
+    article: &'a WikiArticle,
+    warnings: &'a [ParseWarning],
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Output {
+    Json,
+    MessagePack,
+}
+
+fn print_usage() {
+    println!("Usage: rsdctl dump <title or file> [--language <code>] [--source <format>] [--template-rules <file>]");
+    println!("                   [--notes] [--appendices] [--lead-only] [--msgpack]");
+    println!();
+    println!("Reads the article from the file if there is one of that name, otherwise it");
+    println!("is downloaded from Wikipedia. The source format is one of wikitext, html,");
+    println!("markdown and \"plain text\", and is told by the extension of files otherwise.");
+    println!("The parsed article is written to standard output as JSON, or as");
+    println!("MessagePack with --msgpack when built with the msgpack feature.");
+}
+
+fn write_output(dump: &Dump, output: Output) -> Result<()> {
+    let mut stdout = stdout().lock();
+
+    match output {
+        Output::Json => {
+            serde_json::to_writer_pretty(&mut stdout, dump)?;
+            writeln!(stdout)?;
+        }
+
+        #[cfg(feature = "msgpack")]
+        Output::MessagePack => {
+            let bytes = rmp_serde::to_vec_named(dump)?;
+            stdout.write_all(&bytes)?;
+        }
+
+        #[cfg(not(feature = "msgpack"))]
+        Output::MessagePack => {
+            return Err(anyhow!("MessagePack output needs rsdctl built with the msgpack feature"));
+        }
+    }
+
+    Ok(())
+}
+
+// Entry point of the `dump` subcommand
+pub fn run(args: &[String]) -> Result<()> {
+    let mut article = None;
+    let mut language = String::from("en");
+    let mut format = None;
+    let mut config = ParserConfig::default();
+    let mut output = Output::Json;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--appendices" => {
+                config.keep_appendices = true;
+            }
+
+            "--language" => {
+                language = args.next().ok_or(anyhow!("--language needs a language code"))?.clone();
+            }
+
+            "--lead-only" => {
+                config.section_limit = SectionLimit::LeadOnly;
+            }
+
+            "--msgpack" => {
+                output = Output::MessagePack;
+            }
+
+            "--notes" => {
+                config.collect_notes = true;
+            }
+
+            "--source" => {
+                let name = args.next().ok_or(anyhow!("--source needs a format"))?;
+                format = Some(SourceFormat::from_name(name).ok_or(anyhow!("Unknown source format {}", name))?);
+            }
+
+            "--template-rules" => {
+                let path = args.next().ok_or(anyhow!("--template-rules needs a file name"))?;
+                config.templates.load_rules_file(Path::new(path))?;
+            }
+
+            _ if article.is_none() => {
+                article = Some(arg.clone());
+            }
+
+            _ => {
+                print_usage();
+                return Err(anyhow!("Unexpected argument {}", arg));
+            }
+        }
+    }
+
+    let Some(article) = article else {
+        print_usage();
+        return Err(anyhow!("No article given"));
+    };
+
+    let path = Path::new(&article);
+
+    let (title, format, source) = if path.is_file() {
+        let title = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or(anyhow!("Invalid file name {}", path.display()))?
+            .replace('_', " ");
+
+        (title, format.unwrap_or(SourceFormat::for_file(path)), fs::read_to_string(path)?)
+    } else {
+        let page = wikipedia_api::download_article(&language, &article, format.unwrap_or_default())?;
+        (page.title, page.format, page.source)
+    };
+
+    let (wiki_article, warnings) = article_parser::parse_source(&config, format, &language, &title, &source);
+
+    let dump = Dump {
+        language: &language,
+        format: format.name(),
+        article: &wiki_article,
+        warnings: &warnings,
+    };
+
+    write_output(&dump, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    const SOURCE: &str = "{{Infobox animal|name=Cat|legs=4}}
+The '''cat''' is a [[mammal|small mammal]].<ref>A note.</ref><br>New line.
+
+== Kinds ==
+* Domestic
+*# Indoor
+; Wild : Lives outside
+{|
+|+ Legs
+! Animal !! Legs
+|-
+| Cat || 4
+|}
+> Not a quote
+[[File:Cat.jpg|thumb|A cat]]
+ Preformatted  text
+The [[broken link";
+
+    fn parsed() -> (WikiArticle, Vec<ParseWarning>) {
+        let config = ParserConfig { collect_notes: true, ..Default::default() };
+        article_parser::parse_source(&config, SourceFormat::Wikitext, "en", "Cat", SOURCE)
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let (article, warnings) = parsed();
+        assert!(!warnings.is_empty());
+
+        let dump = Dump { language: "en", format: SourceFormat::Wikitext.name(), article: &article, warnings: &warnings };
+        let json: Value = serde_json::from_str(&serde_json::to_string(&dump).unwrap()).unwrap();
+
+        assert_eq!(json["language"], "en");
+        assert_eq!(json["format"], SourceFormat::Wikitext.name());
+
+        let read_article: WikiArticle = serde_json::from_value(json["article"].clone()).unwrap();
+        let read_warnings: Vec<ParseWarning> = serde_json::from_value(json["warnings"].clone()).unwrap();
+
+        assert_eq!(read_article, article);
+        assert_eq!(read_warnings, warnings);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_round_trip() {
+        let (article, _) = parsed();
+
+        let bytes = rmp_serde::to_vec_named(&article).unwrap();
+        let read_article: WikiArticle = rmp_serde::from_slice(&bytes).unwrap();
+
+        assert_eq!(read_article, article);
+    }
+}
@@ -1,13 +1,47 @@
+use std::cell::RefCell;
 use std::sync::Arc;
+use std::time::Duration;
 
 use eframe::egui;
 use eframe::egui::text::Galley;
 use eframe::egui::widgets::*;
-use eframe::epaint::{Color32, text::{LayoutJob, TextFormat}};
+use eframe::epaint::{Color32, Pos2, Stroke, text::{LayoutJob, TextFormat}};
 use egui_notify::{Toasts};
 
-use crate::article_parser::{Token, Section};
+use crate::article_parser::{Token, Section, SectionLimit, SourceFormat, Span, TableCell, WordBoundaries};
+use crate::difficulty::Difficulty;
 use crate::game::{Game, TokenTreatment};
+use crate::multiplayer;
+
+// Longest bit of wiki text shown for a hovered token
+const MAX_SOURCE_CHARS: usize = 500;
+
+// Index of the character under a position relative to the galley
+fn char_index_at(galley: &Galley, pos: Pos2) -> Option<usize> {
+    let mut index = 0;
+
+    for row in &galley.rows {
+        if row.rect.contains(pos) {
+            return row.glyphs
+                .iter()
+                .position(|glyph| glyph.logical_rect().contains(pos))
+                .map(|glyph_index| index + glyph_index);
+        }
+        index += row.char_count_including_newline();
+    }
+    None
+}
+
+// Blanks are as long as the words they hide, so the characters of a
+// rendered galley line up with the tokens
+fn token_at(tokens: &[Token], char_index: usize) -> Option<&Token> {
+    let mut end = 0;
+
+    tokens.iter().find(|token| {
+        end += token.char_count();
+        char_index < end
+    })
+}
 
 struct App {
     game: Game,
@@ -15,9 +49,21 @@ struct App {
     selected_language: String,
 
     title_text_box: String,
+    random_difficulty: Option<Difficulty>,
     toasts: Toasts,
     next_guess: String,
     focus_on_guess: bool,
+
+    multiplayer_mode: multiplayer::Mode,
+    host_address: String,
+    multiplayer_session: Option<multiplayer::Session>,
+
+    // Set when a link in the article is clicked, as the article is
+    // shown without borrowing the app mutably
+    followed_link: RefCell<Option<String>>,
+
+    // Shows the wiki text of hovered tokens, for debugging the parser
+    show_sources: bool,
 }
 
 impl App {
@@ -30,6 +76,7 @@ impl App {
             Ok(()) => {
                 self.next_guess.clear();
                 self.title_text_box.clear();
+                self.warn_about_page_kind();
             }
 
             Err(e) => {
@@ -39,11 +86,94 @@ impl App {
     }
 
     fn load_random_article(&mut self) {
-        let res = self.game.load_random_article();
+        let res = self.game.load_random_article(self.random_difficulty);
 
         match res {
             Ok(()) => {
                 self.selected_language = String::from("en");
+
+                if let Some(warning) = self.game.random_fallback_warning() {
+                    self.toasts.info(warning);
+                }
+            }
+
+            Err(e) => {
+                self.toasts.error(format!("{}", e));
+            }
+        }
+    }
+
+    fn open_file(&mut self) {
+        let picked = rfd::FileDialog::new()
+            .add_filter("Texts", &["md", "markdown", "txt", "html", "htm", "wiki"])
+            .pick_file();
+
+        let Some(path) = picked else { return };
+
+        match self.game.load_file(self.selected_language.as_str(), &path) {
+            Ok(()) => {
+                self.next_guess.clear();
+            }
+
+            Err(e) => {
+                self.toasts.error(format!("{}", e));
+            }
+        }
+    }
+
+    fn warn_about_page_kind(&mut self) {
+        if let Some(warning) = self.game.page_kind.warning() {
+            self.toasts.warning(warning);
+        }
+    }
+
+    fn host_game(&mut self) {
+        match multiplayer::Session::host(self.multiplayer_mode) {
+            Ok(session) => {
+                self.multiplayer_session = Some(session);
+            }
+
+            Err(e) => {
+                self.toasts.error(format!("{}", e));
+            }
+        }
+    }
+
+    fn join_game(&mut self) {
+        match multiplayer::Session::join(self.multiplayer_mode, self.host_address.trim()) {
+            Ok(session) => {
+                self.multiplayer_session = Some(session);
+            }
+
+            Err(e) => {
+                self.toasts.error(format!("{}", e));
+            }
+        }
+    }
+
+    fn poll_multiplayer_session(&mut self, ctx: &egui::Context) {
+        if let Some(session) = &mut self.multiplayer_session {
+            if let Err(e) = session.poll(&mut self.game) {
+                self.toasts.error(format!("{}", e));
+                self.multiplayer_session = None;
+            }
+
+            // Keep polling even if the user does not touch anything
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+    }
+
+    fn can_choose_articles(&self) -> bool {
+        multiplayer::Session::can_choose_articles(self.multiplayer_session.as_ref())
+    }
+
+    fn follow_link(&mut self, target: &str) {
+        let language = self.game.language.clone();
+
+        match self.game.load_article(&language, target) {
+            Ok(()) => {
+                self.next_guess.clear();
+                self.warn_about_page_kind();
             }
 
             Err(e) => {
@@ -52,7 +182,47 @@ impl App {
         }
     }
 
+    fn show_multiplayer_bar(&mut self, ui: &mut egui::Ui) {
+            ui.horizontal(|ui| {
+                ui.label("Player:");
+
+                let player_name = TextEdit::singleline(&mut self.game.player_name)
+                    .desired_width(100.0);
+                ui.add(player_name);
+
+                if let Some(session) = &self.multiplayer_session {
+                    ui.label(session.description());
+
+                    if ui.button("leave").clicked() {
+                        self.multiplayer_session = None;
+                    }
+                } else {
+                    for mode in [multiplayer::Mode::Coop, multiplayer::Mode::Race] {
+                        ui.selectable_value(&mut self.multiplayer_mode, mode, mode.name());
+                    }
+
+                    if ui.button("host").clicked() {
+                        self.host_game();
+                    }
+
+                    ui.label("Host address:");
+
+                    let host_address = TextEdit::singleline(&mut self.host_address)
+                        .desired_width(150.0);
+                    ui.add(host_address);
+
+                    if ui.button("join").clicked() {
+                        self.join_game();
+                    }
+                }
+            });
+    }
+
     fn show_top_bar(&mut self, ui: &mut egui::Ui) {
+            if !self.can_choose_articles() {
+                return;
+            }
+
             ui.horizontal(|ui| {
                 ui.label("Language code:");
 
@@ -79,6 +249,61 @@ impl App {
                 if random_btn.clicked() {
                     self.load_random_article();
                 }
+
+                if ui.button("open file").clicked() {
+                    self.open_file();
+                }
+
+                let selected_difficulty = self.random_difficulty.map_or("any difficulty", |d| d.name());
+
+                egui::ComboBox::from_id_source("random_difficulty")
+                    .selected_text(selected_difficulty)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.random_difficulty, None, "any difficulty");
+
+                        for difficulty in Difficulty::ALL {
+                            ui.selectable_value(&mut self.random_difficulty, Some(difficulty), difficulty.name());
+                        }
+                    });
+
+                // These take effect when the next article is loaded
+                egui::ComboBox::from_id_source("download_format")
+                    .selected_text(self.game.download_format.name())
+                    .show_ui(ui, |ui| {
+                        for format in SourceFormat::DOWNLOADABLE {
+                            ui.selectable_value(&mut self.game.download_format, format, format.name());
+                        }
+                    });
+
+                let section_limit = &mut self.game.parser_config.section_limit;
+
+                egui::ComboBox::from_id_source("section_limit")
+                    .selected_text(section_limit.name())
+                    .show_ui(ui, |ui| {
+                        for limit in SectionLimit::CHOICES {
+                            ui.selectable_value(section_limit, limit, limit.name());
+                        }
+                    });
+
+                ui.checkbox(&mut self.game.parser_config.keep_appendices, "appendices");
+                ui.checkbox(&mut self.game.parser_config.collect_notes, "notes");
+
+                let segmentation = &mut self.game.parser_config.segmentation;
+
+                egui::ComboBox::from_id_source("word_boundaries")
+                    .selected_text(segmentation.boundaries.name())
+                    .show_ui(ui, |ui| {
+                        for boundaries in WordBoundaries::ALL {
+                            ui.selectable_value(&mut segmentation.boundaries, boundaries, boundaries.name());
+                        }
+                    });
+
+                ui.checkbox(&mut segmentation.join_apostrophes, "join apostrophes");
+                ui.checkbox(&mut segmentation.join_hyphens, "join hyphens");
+
+                // The source gives away blanked words
+                ui.add_enabled(self.game.title_complete(), egui::Checkbox::new(&mut self.show_sources, "sources"))
+                    .on_disabled_hover_text("Shown once the title is solved");
             });
     }
 
@@ -109,8 +334,26 @@ impl App {
         let mut job = LayoutJob::default();
         job.wrap.max_width = ui.available_width();
 
+        // There is no bold font, so bold text is drawn in a stronger color
+        let bold_format = TextFormat {
+            color: ui.visuals().strong_text_color(),
+            ..format.clone()
+        };
+
         for token in tokens {
-            self.add_token(&mut job, format, token);
+            let markup = token.markup();
+
+            let mut token_format = TextFormat {
+                italics: format.italics || markup.italic,
+                ..if markup.bold { bold_format.clone() } else { format.clone() }
+            };
+
+            if self.game.link_target(token).is_some() && self.can_choose_articles() {
+                token_format.color = ui.visuals().hyperlink_color;
+                token_format.underline = Stroke::new(1.0, ui.visuals().hyperlink_color);
+            }
+
+            self.add_token(&mut job, &token_format, token);
         }
 
         ui.fonts(|fonts| {
@@ -118,6 +361,63 @@ impl App {
         })
     }
 
+    // Shows tokens as a label, in which links can be clicked once the game is over
+    fn show_tokens(&self, ui: &mut egui::Ui, format: &TextFormat, tokens: &Vec<Token>) {
+        let galley = self.render_tokens(ui, format, tokens);
+
+        let has_links = tokens.iter().any(|token| self.game.link_target(token).is_some());
+        let links_enabled = has_links && self.can_choose_articles();
+
+        if !links_enabled && !self.sources_shown() {
+            ui.label(galley);
+            return;
+        }
+
+        let response = ui.add(egui::Label::new(galley.clone()).sense(egui::Sense::click()));
+
+        let hovered_token = response
+            .hover_pos()
+            .and_then(|pos| char_index_at(&galley, pos - response.rect.min.to_vec2()))
+            .and_then(|index| token_at(tokens, index));
+
+        let hovered_link = hovered_token
+            .filter(|_| links_enabled)
+            .and_then(|token| self.game.link_target(token));
+
+        if let Some(target) = hovered_link {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+
+            if response.clicked() {
+                self.followed_link.replace(Some(String::from(target)));
+            }
+        }
+
+        if let Some(token) = hovered_token.filter(|_| self.sources_shown()) {
+            response.on_hover_ui_at_pointer(|ui| self.show_source(ui, token.span()));
+        }
+    }
+
+    fn sources_shown(&self) -> bool {
+        self.show_sources && self.game.title_complete()
+    }
+
+    fn show_source(&self, ui: &mut egui::Ui, span: Span) {
+        if span.is_empty() {
+            ui.label("Not from the wiki text");
+            return;
+        }
+
+        let source: String = self.game
+            .source_of(span)
+            .unwrap_or("")
+            .chars()
+            .take(MAX_SOURCE_CHARS)
+            .collect();
+
+        ui.label(format!("{}–{}", span.start, span.end));
+        ui.monospace(source);
+    }
+
     fn show_title(&self, ui: &mut egui::Ui, tokens: &Vec<Token>) {
 	let mut font = egui::TextStyle::Monospace.resolve(ui.style());
 	font.size *= 2.0;
@@ -127,8 +427,7 @@ impl App {
             ..Default::default()
 	};
 
-	let galley = self.render_tokens(ui, &title_format, tokens);
-        ui.label(galley);
+	self.show_tokens(ui, &title_format, tokens);
         ui.add_space(30.0);
     }
 
@@ -138,7 +437,7 @@ impl App {
             ..Default::default()
         };
 
-        ui.label(self.render_tokens(ui, &format, tokens));
+        self.show_tokens(ui, &format, tokens);
     }
 
     fn show_sections(&self, ui: &mut egui::Ui, sections: &Vec<Section>) {
@@ -155,8 +454,7 @@ impl App {
 		    };
 
                     ui.add_space(30.0);
-		    let galley = self.render_tokens(ui, &heading_format, tokens);
-                    ui.label(galley);
+		    self.show_tokens(ui, &heading_format, tokens);
                     ui.add_space(10.0);
                 }
 
@@ -192,13 +490,159 @@ impl App {
                         });
                     }
                 }
+
+                Section::Infobox(rows) => {
+                    self.show_infobox(ui, rows);
+                    ui.add_space(10.0);
+                }
+
+                Section::Table(caption, rows) => {
+                    self.show_table(ui, caption, rows);
+                    ui.add_space(10.0);
+                }
+
+                Section::DefinitionList(entries) => {
+                    let term_format = TextFormat {
+                        font_id: egui::TextStyle::Monospace.resolve(ui.style()),
+                        color: ui.visuals().strong_text_color(),
+                        ..Default::default()
+                    };
+
+                    for (term, details) in entries {
+                        if !term.is_empty() {
+                            self.show_tokens(ui, &term_format, term);
+                        }
+
+                        for detail in details {
+                            ui.indent(ui.next_auto_id(), |ui| {
+                                self.show_sections(ui, detail);
+                            });
+                        }
+                    }
+                    ui.add_space(10.0);
+                }
+
+                Section::Quote(sections) => {
+                    ui.indent(ui.next_auto_id(), |ui| {
+                        self.show_sections(ui, sections);
+                    });
+                }
+
+                Section::Preformatted(tokens) => {
+                    egui::Frame::group(ui.style()).show(ui, |ui| {
+                        self.show_paragraph(ui, tokens);
+                    });
+                    ui.add_space(10.0);
+                }
+
+                Section::Figure(tokens) => {
+                    let format = TextFormat {
+                        font_id: egui::TextStyle::Monospace.resolve(ui.style()),
+                        italics: true,
+                        ..Default::default()
+                    };
+
+                    egui::Frame::group(ui.style()).show(ui, |ui| {
+                        self.show_tokens(ui, &format, tokens);
+                    });
+                    ui.add_space(10.0);
+                }
             }
         }
     }
 
+    fn show_infobox(&self, ui: &mut egui::Ui, rows: &Vec<(Vec<Token>, Vec<Token>)>) {
+        let mut label_font = egui::TextStyle::Monospace.resolve(ui.style());
+        label_font.size *= 0.9;
+
+        let label_format = TextFormat {
+            font_id: label_font,
+            color: Color32::GRAY,
+            ..Default::default()
+        };
+
+        let value_format = TextFormat {
+            font_id: egui::TextStyle::Monospace.resolve(ui.style()),
+            ..Default::default()
+        };
+
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            egui::Grid::new(ui.next_auto_id())
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    for (label, value) in rows {
+                        self.show_tokens(ui, &label_format, label);
+                        self.show_tokens(ui, &value_format, value);
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+
+    fn show_table(&self, ui: &mut egui::Ui, caption: &Option<Vec<Token>>, rows: &Vec<Vec<TableCell>>) {
+        let cell_format = TextFormat {
+            font_id: egui::TextStyle::Monospace.resolve(ui.style()),
+            ..Default::default()
+        };
+
+        let header_format = TextFormat {
+            color: ui.visuals().strong_text_color(),
+            ..cell_format.clone()
+        };
+
+        if let Some(caption) = caption {
+            self.show_tokens(ui, &header_format, caption);
+        }
+
+        let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+
+        egui::ScrollArea::horizontal()
+            .id_source(ui.next_auto_id())
+            .show(ui, |ui| {
+                egui::Grid::new(ui.next_auto_id())
+                    .num_columns(columns)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for row in rows {
+                            for cell in row {
+                                let format = if cell.header { &header_format } else { &cell_format };
+                                self.show_tokens(ui, format, &cell.tokens);
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
+    fn show_parse_warnings(&self, ui: &mut egui::Ui) {
+        let warnings = &self.game.parse_warnings;
+
+        if warnings.is_empty() {
+            return;
+        }
+
+        egui::CollapsingHeader::new(format!("Parsed with {} warnings", warnings.len()))
+            .show(ui, |ui| {
+                egui::Grid::new("parse_warnings_grid")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for warning in warnings {
+                            ui.label(format!("{}–{}", warning.start, warning.end));
+                            ui.label(&warning.message);
+                            ui.monospace(self.game.parse_warning_snippet(warning));
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+
     fn show_article(&self, ui: &mut egui::Ui) {
         if let Some(wiki_article) = &self.game.wiki_article {
             egui::ScrollArea::vertical().show(ui, |ui| {
+                self.show_parse_warnings(ui);
+
                 self.show_title(ui, &wiki_article.title);
 
                 self.show_sections(ui, &wiki_article.content);
@@ -206,6 +650,29 @@ impl App {
         }
     }
 
+    fn show_scoreboard(&self, ui: &mut egui::Ui) {
+        let Some(session) = &self.multiplayer_session else { return };
+        let Some((rivals, winner)) = session.scoreboard() else { return };
+
+        if let Some(winner) = winner {
+            ui.label(format!("{} won the race!", winner));
+        }
+
+        egui::Grid::new("scoreboard_grid")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                for rival in rivals {
+                    ui.label(&rival.player);
+                    ui.label(format!("{} guesses", rival.guesses));
+                    ui.label(format!("{:.0}%", rival.progress * 100.0));
+                    ui.end_row();
+                }
+            });
+
+        ui.separator();
+    }
+
     fn show_guesses(&mut self, ui: &mut egui::Ui) {
 
         let next_guess_edit = TextEdit::singleline(&mut self.next_guess);
@@ -226,7 +693,7 @@ impl App {
 
         egui::ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
             egui::Grid::new("guesses_grid")
-                .num_columns(2)
+                .num_columns(3)
                 .striped(true)
                 .show(ui, |ui| {
                     for guess in &self.game.guesses {
@@ -242,6 +709,10 @@ impl App {
                                 self.game.selected_guess = guess.clone();
                             }
                         }
+
+                        if let Some(player) = self.game.guessed_by.get(guess) {
+                            ui.label(player);
+                        }
                         ui.end_row();
                     }
                 });
@@ -251,8 +722,11 @@ impl App {
     fn show_gui(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         ctx.set_pixels_per_point(2.0);
 
+        self.poll_multiplayer_session(ctx);
+
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             self.show_top_bar(ui);
+            self.show_multiplayer_bar(ui);
         });
 
         if let Some(_) = self.game.wiki_article {
@@ -261,6 +735,7 @@ impl App {
                 .resizable(true)
                 .show_separator_line(true)
                 .show(ctx, |ui| {
+                    self.show_scoreboard(ui);
                     self.show_guesses(ui);
                 });
         }
@@ -269,13 +744,17 @@ impl App {
             self.show_article(ui);
         });
 
+        if let Some(target) = self.followed_link.take() {
+            self.follow_link(&target);
+        }
+
         self.toasts.show(ctx);
     }
 }
 
 impl Default for App {
     fn default() -> Self {
-        Self {
+        let mut app = Self {
             game: Game::new(),
 
             selected_language: String::from("en"),
@@ -284,7 +763,22 @@ impl Default for App {
             next_guess: String::from(""),
             focus_on_guess: false,
             title_text_box: String::from(""),
+            random_difficulty: None,
+
+            multiplayer_mode: multiplayer::Mode::Coop,
+            host_address: String::from("localhost"),
+            multiplayer_session: None,
+
+            followed_link: RefCell::new(None),
+
+            show_sources: false,
+        };
+
+        if let Err(e) = app.game.load_template_rules_from_env() {
+            app.toasts.error(format!("{}", e));
         }
+
+        app
     }
 }
 
@@ -1,10 +1,30 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 use crate::article_parser;
-use crate::article_parser::{WikiArticle, Section, Token};
+use crate::article_parser::{WikiArticle, PageKind, ParseWarning, ParserConfig, Section, SourceFormat, Span, Token};
+use crate::difficulty;
+use crate::difficulty::Difficulty;
 use crate::wikipedia_api;
+use crate::wikipedia_api::Page;
+use crate::word_frequency::WordFrequency;
+
+// Names a file with extra template rules for the parser
+const TEMPLATE_RULES_VARIABLE: &str = "RSDCTL_TEMPLATE_RULES";
+
+// How many random articles to try before settling for one of a
+// different difficulty than requested, or giving up on finding
+// one that is not a disambiguation page or list
+const MAX_RANDOM_ATTEMPTS: usize = 10;
+
+// Guesses and words are compared in lower case, and with typographic
+// apostrophes made plain, so "don't" can be typed for "don’t"
+fn normalize_word(word: &str) -> String {
+    word.to_lowercase().replace('’', "'")
+}
 
 pub enum TokenTreatment {
     Blank,
@@ -14,8 +34,32 @@ pub enum TokenTreatment {
 
 pub struct Game {
     pub wiki_article: Option<WikiArticle>,
+    pub parse_warnings: Vec<ParseWarning>,
+
+    // Frontends warn when this is not a proper article
+    pub page_kind: PageKind,
+
+    // The requested and actual difficulty, and how many articles were
+    // rated, when no random article of the requested one was found
+    random_fallback: Option<(Difficulty, Difficulty, usize)>,
+
+    // Frontends may register more template handlers here
+    pub parser_config: ParserConfig,
+
+    // What new articles are downloaded as
+    pub download_format: SourceFormat,
+
     pub guesses: BTreeSet<String>,
 
+    // Who made each guess; empty outside of multiplayer games.
+    pub guessed_by: BTreeMap<String, String>,
+    pub player_name: String,
+
+    // Where the current article came from, so it can be passed on to other players.
+    pub language: String,
+    pub source: String,
+    pub source_format: SourceFormat,
+
     pub selected_guess: String,
 }
 
@@ -23,53 +67,177 @@ impl Game {
     pub fn new() -> Self {
 	Game {
 	    wiki_article: None,
+	    parse_warnings: Vec::new(),
+	    page_kind: PageKind::Article,
+	    random_fallback: None,
+	    parser_config: ParserConfig::default(),
+	    download_format: SourceFormat::default(),
 	    guesses: BTreeSet::new(),
+	    guessed_by: BTreeMap::new(),
+	    player_name: String::from(""),
+	    language: String::from(""),
+	    source: String::from(""),
+	    source_format: SourceFormat::default(),
 	    selected_guess: String::from(""),
 	}
     }
 
+    pub fn load_template_rules_from_env(&mut self) -> Result<()> {
+        match std::env::var_os(TEMPLATE_RULES_VARIABLE) {
+            Some(path) => self.parser_config.templates.load_rules_file(Path::new(&path)),
+            None => Ok(()),
+        }
+    }
+
     pub fn load_article(&mut self, language: &str, title: &str) -> Result<()> {
-	let downloaded = wikipedia_api::download_article(language, title);
-
-	// TODO monad?
-        match downloaded {
-            Ok((title, content)) => {
-                self.wiki_article = Some(article_parser::parse(title.as_str(), content.as_str()));
-                self.guesses.clear();
-		self.selected_guess.clear();
-		Ok(())
-            }
+	let page = wikipedia_api::download_article(language, title, self.download_format)?;
 
-            Err(e) => {
-		Err(e)
-            }
-        }
+	self.load_source(language, page.title.as_str(), page.format, page.source.as_str());
+
+	if page.disambiguation {
+	    self.page_kind = PageKind::Disambiguation;
+	}
+	Ok(())
+    }
+
+    // Custom texts, in the format their extension tells
+    pub fn load_file(&mut self, language: &str, path: &Path) -> Result<()> {
+        let source = fs::read_to_string(path)?;
+
+        let title = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or(anyhow!("Invalid file name {}", path.display()))?
+            .replace('_', " ");
+
+        self.load_source(language, &title, SourceFormat::for_file(path), &source);
+        Ok(())
+    }
+
+    pub fn load_source(&mut self, language: &str, title: &str, format: SourceFormat, source: &str) {
+        let (wiki_article, parse_warnings) = article_parser::parse_source(&self.parser_config, format, language, title, source);
+        self.load_parsed(language, title, format, source, wiki_article, parse_warnings);
     }
 
-    pub fn load_random_article(&mut self) -> Result<()> {
-	let article = wikipedia_api::random_english_article();
+    // Takes an article parsed elsewhere, so players in a multiplayer game
+    // all see it the way the host's parser settings made it.
+    pub fn load_parsed(&mut self, language: &str, title: &str, format: SourceFormat, source: &str, wiki_article: WikiArticle, parse_warnings: Vec<ParseWarning>) {
+        self.wiki_article = Some(wiki_article);
+        self.parse_warnings = parse_warnings;
+        self.page_kind = article_parser::page_kind(title, format, source);
+        self.random_fallback = None;
+        self.language = String::from(language);
+        self.source = String::from(source);
+        self.source_format = format;
+        self.guesses.clear();
+        self.guessed_by.clear();
+        self.selected_guess.clear();
+    }
+
+    pub fn load_random_article(&mut self, difficulty: Option<Difficulty>) -> Result<()> {
+	let frequency = WordFrequency::builtin();
+
+	// Keep the article closest to the requested difficulty, in case
+	// we do not find one that matches
+	let mut closest: Option<(f32, f32, Page)> = None;
+
+	// Disambiguation pages and lists are skipped without rating them
+	let mut rated = 0;
+
+	for _ in 0..MAX_RANDOM_ATTEMPTS {
+	    let name = wikipedia_api::random_english_article()?;
+	    let page = wikipedia_api::download_article("en", name.as_str(), self.download_format)?;
+
+	    let kind = article_parser::page_kind(page.title.as_str(), page.format, page.source.as_str());
+	    if page.disambiguation || kind != PageKind::Article {
+		continue;
+	    }
+
+	    let (distance, score) = match difficulty {
+		Some(difficulty) => {
+		    let (article, _) = article_parser::parse_source(&self.parser_config, page.format, "en", page.title.as_str(), page.source.as_str());
+		    let score = difficulty::estimate(&article, &frequency);
+		    rated += 1;
+		    (difficulty.distance(score), score)
+		}
+
+		None => (0.0, 0.0),
+	    };
 
-	// TODO monad?
-	match article {
-	    Ok(name) => {
-		self.load_article("en", name.as_str())
+	    if closest.as_ref().is_none_or(|(d, ..)| distance < *d) {
+		closest = Some((distance, score, page));
 	    }
 
-	    Err(e) => {
-		Err(e)
+	    if distance == 0.0 {
+		break;
 	    }
 	}
+
+	match closest {
+	    Some((distance, score, page)) => {
+		self.load_source("en", page.title.as_str(), page.format, page.source.as_str());
+
+		if let (Some(difficulty), true) = (difficulty, distance > 0.0) {
+		    self.random_fallback = Some((difficulty, Difficulty::of_score(score), rated));
+		}
+		Ok(())
+	    }
+
+	    None => Err(anyhow!("Only found disambiguation pages and lists, please try again")),
+	}
+    }
+
+    // Frontends tell players when the random article is not of the difficulty they asked for
+    pub fn random_fallback_warning(&self) -> Option<String> {
+        let (requested, actual, rated) = self.random_fallback?;
+        let articles = if rated == 1 { "article" } else { "articles" };
+        Some(format!("None of the {} {} rated was {}, this one is {}", rated, articles, requested.name(), actual.name()))
     }
 
     pub fn guess(&mut self, raw_guess: &str) {
-	self.guesses.insert(raw_guess.trim().to_lowercase());
+        let player = self.player_name.clone();
+        self.guess_as(&player, raw_guess);
+    }
+
+    pub fn guess_as(&mut self, player: &str, raw_guess: &str) {
+        let guess = normalize_word(raw_guess.trim());
+
+        if self.guesses.insert(guess.clone()) && !player.is_empty() {
+            self.guessed_by.insert(guess, String::from(player));
+        }
+    }
+
+    pub fn title(&self) -> Option<String> {
+        self.wiki_article.as_ref().map(|wiki_article| {
+            wiki_article.title.iter().map(|token| token.get_str()).collect()
+        })
     }
 
-    fn title_complete(&self) -> bool {
+    // The wiki text of a parse warning may give away blanked words, so
+    // it is only shown once the title is solved
+    pub fn parse_warning_snippet<'a>(&self, warning: &'a ParseWarning) -> &'a str {
+        if self.title_complete() {
+            &warning.snippet
+        } else {
+            "(shown once the title is solved)"
+        }
+    }
+
+    // The source text of a token gives away blanked words just like
+    // warning snippets do, so it is also hidden until the title is solved
+    pub fn source_of(&self, span: Span) -> Option<&str> {
+        if self.title_complete() {
+            self.source.get(span.start..span.end)
+        } else {
+            None
+        }
+    }
+
+    pub fn title_complete(&self) -> bool {
         if let Some(wiki_article) = &self.wiki_article {
             for token in &wiki_article.title {
-                if let Token::Word(w) = token {
-                    if !self.guesses.contains(&w.to_lowercase()) {
+                if let Token::Word(w, ..) = token {
+                    if !self.guesses.contains(&normalize_word(w)) {
                         return false;
                     }
                 }
@@ -81,19 +249,28 @@ impl Game {
         }
     }
 
+    // Links can only be followed once the game is over
+    pub fn link_target<'t>(&self, token: &'t Token) -> Option<&'t str> {
+        if self.title_complete() {
+            token.markup().link.as_deref()
+        } else {
+            None
+        }
+    }
+
     pub fn get_token_treatment(&self, token: &Token) -> TokenTreatment {
 	match token {
-	    Token::Word(word) => {
-		if self.selected_guess.to_lowercase() == word.to_lowercase() {
+	    Token::Word(word, ..) => {
+		if normalize_word(&self.selected_guess) == normalize_word(word) {
 		    TokenTreatment::Highlight
-		} else if self.guesses.contains(&word.to_lowercase()) || self.title_complete() {
+		} else if self.guesses.contains(&normalize_word(word)) || self.title_complete() {
 		    TokenTreatment::Show
 		} else {
 		    TokenTreatment::Blank
 		}
 	    }
 
-	    Token::NonWord(_) => {
+	    Token::NonWord(..) | Token::LineBreak(_) => {
 		TokenTreatment::Show
 	    }
         }
@@ -103,8 +280,8 @@ impl Game {
         let mut result = 0;
 
         for token in tokens {
-            if let Token::Word(w) = token {
-                if w.to_lowercase() == word.to_lowercase() {
+            if let Token::Word(w, ..) = token {
+                if normalize_word(w) == normalize_word(word) {
                     result += 1;
                 }
             }
@@ -121,10 +298,24 @@ impl Game {
                     result += Self::count_word_in_tokens(word, tokens);
                 }
 
-                Section::Paragraph(tokens) => {
+                Section::Paragraph(tokens) | Section::Figure(tokens) | Section::Preformatted(tokens) => {
                     result += Self::count_word_in_tokens(word, tokens);
                 }
 
+                Section::DefinitionList(entries) => {
+                    for (term, details) in entries {
+                        result += Self::count_word_in_tokens(word, term);
+
+                        for detail in details {
+                            result += Self::count_word_in_sections(word, detail);
+                        }
+                    }
+                }
+
+                Section::Quote(sections) => {
+                    result += Self::count_word_in_sections(word, sections);
+                }
+
                 Section::UnorderedList(list_items) => {
                     for item in list_items {
                         result += Self::count_word_in_sections(word, item);
@@ -136,17 +327,189 @@ impl Game {
                         result += Self::count_word_in_sections(word, item);
                     }
                 }
+
+                Section::Infobox(rows) => {
+                    for (label, value) in rows {
+                        result += Self::count_word_in_tokens(word, label);
+                        result += Self::count_word_in_tokens(word, value);
+                    }
+                }
+
+                Section::Table(caption, rows) => {
+                    if let Some(caption) = caption {
+                        result += Self::count_word_in_tokens(word, caption);
+                    }
+
+                    for cell in rows.iter().flatten() {
+                        result += Self::count_word_in_tokens(word, &cell.tokens);
+                    }
+                }
             }
         }
         result
     }
 
-    pub fn count_word_in_article(&self, word: &str) -> Option<usize> {
+    fn count_revealed_in_tokens(&self, tokens: &Vec<Token>) -> (usize, usize) {
+        let mut revealed = 0;
+        let mut total = 0;
+
+        for token in tokens {
+            if let Token::Word(..) = token {
+                total += 1;
+                if !matches!(self.get_token_treatment(token), TokenTreatment::Blank) {
+                    revealed += 1;
+                }
+            }
+        }
+        (revealed, total)
+    }
+
+    fn count_revealed_in_sections(&self, sections: &Vec<Section>) -> (usize, usize) {
+        let mut revealed = 0;
+        let mut total = 0;
+
+        for section in sections {
+            let (r, t) = match section {
+                Section::Heading(_level, tokens) => {
+                    self.count_revealed_in_tokens(tokens)
+                }
+
+                Section::Paragraph(tokens) | Section::Figure(tokens) | Section::Preformatted(tokens) => {
+                    self.count_revealed_in_tokens(tokens)
+                }
+
+                Section::DefinitionList(entries) => {
+                    let mut r = 0;
+                    let mut t = 0;
+                    for (term, details) in entries {
+                        let (term_r, term_t) = self.count_revealed_in_tokens(term);
+                        r += term_r;
+                        t += term_t;
+
+                        for detail in details {
+                            let (detail_r, detail_t) = self.count_revealed_in_sections(detail);
+                            r += detail_r;
+                            t += detail_t;
+                        }
+                    }
+                    (r, t)
+                }
+
+                Section::Quote(sections) => {
+                    self.count_revealed_in_sections(sections)
+                }
+
+                Section::UnorderedList(list_items) | Section::OrderedList(list_items) => {
+                    let mut r = 0;
+                    let mut t = 0;
+                    for item in list_items {
+                        let (item_r, item_t) = self.count_revealed_in_sections(item);
+                        r += item_r;
+                        t += item_t;
+                    }
+                    (r, t)
+                }
+
+                Section::Infobox(rows) => {
+                    let mut r = 0;
+                    let mut t = 0;
+                    for (label, value) in rows {
+                        let (label_r, label_t) = self.count_revealed_in_tokens(label);
+                        let (value_r, value_t) = self.count_revealed_in_tokens(value);
+                        r += label_r + value_r;
+                        t += label_t + value_t;
+                    }
+                    (r, t)
+                }
+
+                Section::Table(caption, rows) => {
+                    let (mut r, mut t) = match caption {
+                        Some(caption) => self.count_revealed_in_tokens(caption),
+                        None => (0, 0),
+                    };
+                    for cell in rows.iter().flatten() {
+                        let (cell_r, cell_t) = self.count_revealed_in_tokens(&cell.tokens);
+                        r += cell_r;
+                        t += cell_t;
+                    }
+                    (r, t)
+                }
+            };
+            revealed += r;
+            total += t;
+        }
+        (revealed, total)
+    }
+
+    // Fraction of the words in the article that are no longer blanked out
+    pub fn progress(&self) -> f32 {
         if let Some(wiki_article) = &self.wiki_article {
-            Some(Self::count_word_in_tokens(word, &wiki_article.title)
-		 + Self::count_word_in_sections(word, &wiki_article.content))
-	} else {
-            None
+            let (title_revealed, title_total) = self.count_revealed_in_tokens(&wiki_article.title);
+            let (content_revealed, content_total) = self.count_revealed_in_sections(&wiki_article.content);
+
+            let total = title_total + content_total;
+            if total == 0 {
+                return 0.0;
+            }
+            (title_revealed + content_revealed) as f32 / total as f32
+        } else {
+            0.0
         }
     }
+
+    pub fn count_word_in_article(&self, word: &str) -> Option<usize> {
+        self.wiki_article.as_ref().map(|wiki_article| {
+            Self::count_word_in_tokens(word, &wiki_article.title)
+                + Self::count_word_in_sections(word, &wiki_article.content)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warning_snippets_hidden_until_solved() {
+        let mut game = Game::new();
+        game.load_source("en", "Secret", SourceFormat::Wikitext, "The [[secret is secret.");
+
+        let warning = game.parse_warnings.first().cloned().expect("no parse warning");
+        assert!(!game.parse_warning_snippet(&warning).contains("secret"));
+
+        game.guess("secret");
+        assert_eq!(game.parse_warning_snippet(&warning), "[[secret is secret.");
+    }
+
+    #[test]
+    fn sources_hidden_until_solved() {
+        let mut game = Game::new();
+        game.load_source("en", "Cat", SourceFormat::Wikitext, "The '''cat''' is a [[Felidae|felid]].");
+
+        let span = game.wiki_article.as_ref().unwrap().title[0].span();
+        let body = &game.wiki_article.as_ref().unwrap().content[0];
+        let felid = body.tokens().into_iter().find(|token| token.get_str() == "felid").unwrap().span();
+
+        assert_eq!(game.source_of(felid), None);
+
+        game.guess("cat");
+        assert_eq!(game.source_of(felid), Some("[[Felidae|felid]]"));
+        assert_eq!(game.source_of(span), Some(""));
+    }
+
+    #[test]
+    fn fallback_warning_counts_rated_articles() {
+        let mut game = Game::new();
+        assert_eq!(game.random_fallback_warning(), None);
+
+        game.random_fallback = Some((Difficulty::Easy, Difficulty::Hard, 1));
+        assert_eq!(game.random_fallback_warning().as_deref(), Some("None of the 1 article rated was easy, this one is hard"));
+
+        game.random_fallback = Some((Difficulty::Easy, Difficulty::Medium, 7));
+        assert_eq!(game.random_fallback_warning().as_deref(), Some("None of the 7 articles rated was easy, this one is medium"));
+
+        // A new article is not a fallback anymore
+        game.load_source("en", "Cat", SourceFormat::Wikitext, "A cat.");
+        assert_eq!(game.random_fallback_warning(), None);
+    }
 }
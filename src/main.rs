@@ -4,15 +4,25 @@ use std::io::stdin;
 use std::io::stdout;
 use std::io::Write;
 
+use anyhow::{anyhow, Result};
+
 use termion::event::Key;
 use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 
 mod article_parser;
+mod coop;
+mod difficulty;
 mod dioxus;
+mod dump;
 mod egui;
 mod game;
+mod multiplayer;
+mod net;
+mod race;
+mod solver;
 mod wikipedia_api;
+mod word_frequency;
 
 use crate::article_parser::{Section, Token};
 
@@ -20,14 +30,17 @@ use crate::article_parser::{Section, Token};
 fn print_tokens(tokens: &Vec<Token>) {
     for token in tokens {
         match token {
-            Token::Word(w) => {
+            Token::Word(w, ..) => {
                 let blanked: String = std::iter::repeat('_').take(w.len()).collect();
                 print!("{}", blanked);
                 // print!("{}", w);
             }
-            Token::NonWord(w) => {
+            Token::NonWord(w, ..) => {
                 print!("{}", w);
             }
+            Token::LineBreak(_) => {
+                println!();
+            }
         }
     }
 }
@@ -64,11 +77,84 @@ fn print_sections(sections: &Vec<Section>) {
                     print_sections(item);
                 }
             }
+
+            Section::Infobox(rows) => {
+                for (label, value) in rows {
+                    print!(" | ");
+                    print_tokens(label);
+                    print!(": ");
+                    print_tokens(value);
+                    println!();
+                }
+                println!();
+            }
+
+            Section::DefinitionList(entries) => {
+                for (term, details) in entries {
+                    if !term.is_empty() {
+                        print_tokens(term);
+                        println!();
+                    }
+                    for detail in details {
+                        print!("    ");
+                        print_sections(detail);
+                    }
+                }
+            }
+
+            Section::Quote(sections) => {
+                print!(" > ");
+                print_sections(sections);
+            }
+
+            Section::Preformatted(tokens) => {
+                print_tokens(tokens);
+                print!("\n\n");
+            }
+
+            Section::Figure(tokens) => {
+                print!("[ ");
+                print_tokens(tokens);
+                print!(" ]\n\n");
+            }
+
+            Section::Table(caption, rows) => {
+                if let Some(caption) = caption {
+                    print_tokens(caption);
+                    println!();
+                }
+                for row in rows {
+                    for cell in row {
+                        print!(" | ");
+                        print_tokens(&cell.tokens);
+                    }
+                    println!(" |");
+                }
+                println!();
+            }
         }
     }
 }
 
+fn run_subcommand(args: &[String]) -> Result<()> {
+    match args[0].as_str() {
+        "dump" => dump::run(&args[1..]),
+        "solve" => solver::run(&args[1..]),
+        _ => Err(anyhow!("Unknown subcommand {}", args[0])),
+    }
+}
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if !args.is_empty() {
+        if let Err(e) = run_subcommand(&args) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let mut stdout = stdout().into_raw_mode().unwrap();
 
     println!("Welcome to RsDactl\r");
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::article_parser::{ParseWarning, SourceFormat, WikiArticle};
+use crate::coop;
+use crate::game::Game;
+use crate::net;
+use crate::race;
+use crate::race::Rival;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Coop,
+    Race,
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Coop => "co-op",
+            Mode::Race => "race",
+        }
+    }
+}
+
+// Both modes start a game by sending the host's article to all clients
+pub fn article_message(game: &Game) -> Value {
+    json!({
+        "type": "article",
+        "language": game.language,
+        "title": game.title().unwrap_or_default(),
+        "format": game.source_format.name(),
+        "source": game.source,
+
+        // Clients use the host's parse rather than their own settings
+        "article": game.wiki_article,
+        "warnings": game.parse_warnings,
+    })
+}
+
+pub fn load_article_message(game: &mut Game, message: &Value) {
+    let format = message["format"]
+        .as_str()
+        .and_then(SourceFormat::from_name)
+        .unwrap_or_default();
+
+    let language = message["language"].as_str().unwrap_or("");
+    let title = message["title"].as_str().unwrap_or("");
+    let source = message["source"].as_str().unwrap_or("");
+
+    // Hosts without a parsed article to share make us parse the source ourselves
+    let article = WikiArticle::deserialize(&message["article"]);
+    let warnings = Vec::<ParseWarning>::deserialize(&message["warnings"]);
+
+    match (article, warnings) {
+        (Ok(article), Ok(warnings)) => game.load_parsed(language, title, format, source, article, warnings),
+        _ => game.load_source(language, title, format, source),
+    }
+}
+
+pub enum Session {
+    Coop(coop::Session),
+    Race(race::Session),
+}
+
+impl Session {
+    pub fn host(mode: Mode) -> Result<Session> {
+        match mode {
+            Mode::Coop => {
+                let host = coop::Host::start(net::DEFAULT_PORT)?;
+                Ok(Session::Coop(coop::Session::Host(host)))
+            }
+
+            Mode::Race => {
+                let host = race::Host::start(net::DEFAULT_PORT)?;
+                Ok(Session::Race(race::Session::Host(host)))
+            }
+        }
+    }
+
+    pub fn join(mode: Mode, address: &str) -> Result<Session> {
+        match mode {
+            Mode::Coop => {
+                let client = coop::Client::connect(address)?;
+                Ok(Session::Coop(coop::Session::Client(client)))
+            }
+
+            Mode::Race => {
+                let client = race::Client::connect(address)?;
+                Ok(Session::Race(race::Session::Client(client)))
+            }
+        }
+    }
+
+    pub fn poll(&mut self, game: &mut Game) -> Result<bool> {
+        match self {
+            Session::Coop(session) => session.poll(game),
+            Session::Race(session) => session.poll(game),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Session::Coop(session) => session.description(),
+            Session::Race(session) => session.description(),
+        }
+    }
+
+    pub fn is_client(&self) -> bool {
+        match self {
+            Session::Coop(session) => session.is_client(),
+            Session::Race(session) => session.is_client(),
+        }
+    }
+
+    // Only the host may choose articles in a multiplayer game, by
+    // loading them or following links
+    pub fn can_choose_articles(session: Option<&Session>) -> bool {
+        !session.is_some_and(Session::is_client)
+    }
+
+    pub fn scoreboard(&self) -> Option<(&Vec<Rival>, Option<&String>)> {
+        match self {
+            Session::Coop(_) => None,
+            Session::Race(session) => Some((session.scoreboard(), session.winner())),
+        }
+    }
+}
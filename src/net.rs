@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::Value;
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+// How long the accept loop sleeps between checks for new connections
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+
+// Messages are sent from the GUI thread, so a peer that stops reading
+// may only hold it up this long before the write fails
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Messages are JSON objects, one per line, with a "type" field saying
+// what kind of message they are.
+
+pub enum Event {
+    Connected(usize, Connection),
+    Message(usize, Value),
+    Disconnected(usize),
+}
+
+// The writing end of a connection. The reader thread holds a clone of
+// the same socket, so dropping a connection shuts the socket down to
+// make sure the other side notices and the reader thread ends.
+pub struct Connection {
+    stream: TcpStream,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Result<Connection> {
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        Ok(Connection { stream })
+    }
+
+    // Fails once the peer has not read anything for WRITE_TIMEOUT, which
+    // leaves part of a message behind, so the connection should be dropped
+    pub fn send(&mut self, message: &Value) -> Result<()> {
+        let mut line = serde_json::to_string(message)?;
+        line.push('\n');
+        self.stream.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn spawn_reader(id: usize, stream: TcpStream, events: Sender<Event>) {
+    thread::spawn(move || {
+        let reader = BufReader::new(stream);
+
+        for line in reader.lines() {
+            let Ok(line) = line else { break };
+
+            // Ignore anything that is not valid JSON rather than dropping the connection
+            if let Ok(message) = serde_json::from_str(&line) {
+                if events.send(Event::Message(id, message)).is_err() {
+                    return;
+                }
+            }
+        }
+
+        let _ = events.send(Event::Disconnected(id));
+    });
+}
+
+// Accepts connections on a background thread until dropped. Each new
+// connection is announced with an Event::Connected carrying a connection
+// to write to, and all incoming messages are tagged with the id of the
+// connection.
+pub struct Listener {
+    pub port: u16,
+    pub events: Receiver<Event>,
+
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        // Wait for the thread so the port is free again once we return
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Port 0 picks any free port, the one actually used is in Listener::port.
+pub fn listen(port: u16) -> Result<Listener> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    let port = listener.local_addr()?.port();
+
+    // Accepting without blocking lets the thread notice when it should stop
+    listener.set_nonblocking(true)?;
+
+    let (sender, receiver) = channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let thread_stop = stop.clone();
+
+    let thread = thread::spawn(move || {
+        let mut next_id = 0;
+
+        while !thread_stop.load(Ordering::Relaxed) {
+            let stream = match listener.accept() {
+                Ok((stream, _address)) => stream,
+
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(ACCEPT_INTERVAL);
+                    continue;
+                }
+
+                Err(_) => continue,
+            };
+
+            let id = next_id;
+            next_id += 1;
+
+            if stream.set_nonblocking(false).is_err() {
+                continue;
+            }
+            let Ok(reader_stream) = stream.try_clone() else { continue };
+            let Ok(connection) = Connection::new(stream) else { continue };
+
+            if sender.send(Event::Connected(id, connection)).is_err() {
+                return;
+            }
+
+            spawn_reader(id, reader_stream, sender.clone());
+        }
+    });
+
+    Ok(Listener {
+        port,
+        events: receiver,
+        stop,
+        thread: Some(thread),
+    })
+}
+
+// Connections we cannot write to anymore are dropped
+pub fn broadcast(connections: &mut BTreeMap<usize, Connection>, message: &Value) {
+    connections.retain(|_id, connection| connection.send(message).is_ok());
+}
+
+pub fn connect(address: &str) -> Result<(Connection, Receiver<Event>)> {
+    let stream = if address.contains(':') {
+        TcpStream::connect(address)?
+    } else {
+        TcpStream::connect((address, DEFAULT_PORT))?
+    };
+
+    let (sender, receiver) = channel();
+    spawn_reader(0, stream.try_clone()?, sender);
+
+    Ok((Connection::new(stream)?, receiver))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    // Polls until the condition holds, giving the network threads some time
+    pub fn poll_until(mut poll: impl FnMut() -> bool) -> bool {
+        let start = Instant::now();
+
+        while start.elapsed() < Duration::from_secs(5) {
+            if poll() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn peers_that_stop_reading_are_dropped() {
+        let listener = listen(0).unwrap();
+        let _stalled = TcpStream::connect(("127.0.0.1", listener.port)).unwrap();
+
+        let mut connections = BTreeMap::new();
+        assert!(poll_until(|| match listener.events.try_recv() {
+            Ok(Event::Connected(id, connection)) => connections.insert(id, connection).is_none(),
+            _ => false,
+        }));
+
+        // Fills the socket buffers until a write times out
+        let message = Value::String("x".repeat(100_000));
+        let start = Instant::now();
+        while !connections.is_empty() && start.elapsed() < Duration::from_secs(30) {
+            let sent = Instant::now();
+            broadcast(&mut connections, &message);
+            assert!(sent.elapsed() < WRITE_TIMEOUT * 3);
+        }
+
+        assert!(connections.is_empty());
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc::Receiver;
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+
+use crate::game::Game;
+use crate::multiplayer;
+use crate::net;
+use crate::net::{Connection, Event, Listener};
+
+// Competitive play: every player gets the same article from the host
+// but plays their own game. Players only report how far they got, so
+// the scoreboard never gives away anybody's guesses. The first player
+// to complete the title wins.
+
+#[derive(Clone)]
+pub struct Rival {
+    pub player: String,
+    pub guesses: usize,
+    pub progress: f32,
+    pub solved: bool,
+}
+
+impl Rival {
+    fn of_game(game: &Game) -> Rival {
+        Rival {
+            player: game.player_name.clone(),
+            guesses: game.guesses.len(),
+            progress: game.progress(),
+            solved: game.title_complete(),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "player": self.player,
+            "guesses": self.guesses,
+            "progress": self.progress,
+            "solved": self.solved,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Rival> {
+        Some(Rival {
+            player: String::from(value["player"].as_str()?),
+            guesses: value["guesses"].as_u64()? as usize,
+            progress: value["progress"].as_f64()? as f32,
+            solved: value["solved"].as_bool()?,
+        })
+    }
+
+    fn same_score(&self, other: &Rival) -> bool {
+        self.player == other.player && self.guesses == other.guesses && self.solved == other.solved
+    }
+}
+
+pub enum Session {
+    Host(Host),
+    Client(Client),
+}
+
+impl Session {
+    // Returns whether the game or the scoreboard was changed by other players.
+    pub fn poll(&mut self, game: &mut Game) -> Result<bool> {
+        match self {
+            Session::Host(host) => Ok(host.poll(game)),
+            Session::Client(client) => client.poll(game),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Session::Host(host) => {
+                format!("Hosting race on port {} ({} rivals joined)", host.listener.port, host.clients.len())
+            }
+
+            Session::Client(client) => {
+                format!("Racing at {}", client.address)
+            }
+        }
+    }
+
+    pub fn is_client(&self) -> bool {
+        matches!(self, Session::Client(_))
+    }
+
+    pub fn scoreboard(&self) -> &Vec<Rival> {
+        match self {
+            Session::Host(host) => &host.scoreboard,
+            Session::Client(client) => &client.scoreboard,
+        }
+    }
+
+    pub fn winner(&self) -> Option<&String> {
+        match self {
+            Session::Host(host) => host.winner.as_ref(),
+            Session::Client(client) => client.winner.as_ref(),
+        }
+    }
+}
+
+// The host uses this id for its own entry in the scoreboard
+const HOST_ID: usize = usize::MAX;
+
+pub struct Host {
+    listener: Listener,
+    clients: BTreeMap<usize, Connection>,
+
+    shared_title: Option<String>,
+    rivals: BTreeMap<usize, Rival>,
+    scoreboard: Vec<Rival>,
+    winner: Option<String>,
+}
+
+impl Host {
+    pub fn start(port: u16) -> Result<Host> {
+        Ok(Host {
+            listener: net::listen(port)?,
+            clients: BTreeMap::new(),
+            shared_title: None,
+            rivals: BTreeMap::new(),
+            scoreboard: Vec::new(),
+            winner: None,
+        })
+    }
+
+    fn scoreboard_message(&self) -> Value {
+        json!({
+            "type": "scoreboard",
+            "rivals": self.scoreboard.iter().map(Rival::to_json).collect::<Vec<Value>>(),
+            "winner": self.winner,
+        })
+    }
+
+    fn update_rival(&mut self, id: usize, rival: Rival) -> bool {
+        if rival.solved && self.winner.is_none() {
+            self.winner = Some(rival.player.clone());
+        }
+
+        let unchanged = self.rivals.get(&id).is_some_and(|old| old.same_score(&rival));
+        self.rivals.insert(id, rival);
+        !unchanged
+    }
+
+    pub fn poll(&mut self, game: &mut Game) -> bool {
+        let mut changed = false;
+
+        let title = game.title();
+        if title.is_some() && title != self.shared_title {
+            // A new race starts with the new article
+            self.shared_title = title;
+            self.rivals.clear();
+            self.winner = None;
+            net::broadcast(&mut self.clients, &multiplayer::article_message(game));
+            changed = true;
+        }
+
+        while let Ok(event) = self.listener.events.try_recv() {
+            match event {
+                Event::Connected(id, mut connection) => {
+                    if self.shared_title.is_none() || connection.send(&multiplayer::article_message(game)).is_ok() {
+                        self.clients.insert(id, connection);
+                        changed = true;
+                    }
+                }
+
+                Event::Message(id, message) => {
+                    // Progress sent before the client loaded the current
+                    // article belongs to an earlier race
+                    let current = self.shared_title.is_some()
+                        && message["title"].as_str() == self.shared_title.as_deref();
+
+                    if current && message["type"] == "progress" {
+                        if let Some(rival) = Rival::from_json(&message) {
+                            changed |= self.update_rival(id, rival);
+                        }
+                    }
+                }
+
+                Event::Disconnected(id) => {
+                    self.clients.remove(&id);
+                    changed |= self.rivals.remove(&id).is_some();
+                }
+            }
+        }
+
+        if self.shared_title.is_some() {
+            changed |= self.update_rival(HOST_ID, Rival::of_game(game));
+        }
+
+        if changed {
+            self.scoreboard = self.rivals.values().cloned().collect();
+            self.scoreboard.sort_by(|a, b| b.progress.total_cmp(&a.progress));
+            let message = self.scoreboard_message();
+            net::broadcast(&mut self.clients, &message);
+        }
+
+        changed
+    }
+}
+
+pub struct Client {
+    address: String,
+    connection: Connection,
+    events: Receiver<Event>,
+
+    // Whether the host has sent us the article to race on yet
+    racing: bool,
+    reported: Option<Rival>,
+    scoreboard: Vec<Rival>,
+    winner: Option<String>,
+}
+
+impl Client {
+    pub fn connect(address: &str) -> Result<Client> {
+        let (connection, events) = net::connect(address)?;
+
+        Ok(Client {
+            address: String::from(address),
+            connection,
+            events,
+            racing: false,
+            reported: None,
+            scoreboard: Vec::new(),
+            winner: None,
+        })
+    }
+
+    pub fn poll(&mut self, game: &mut Game) -> Result<bool> {
+        let mut changed = false;
+
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Event::Message(_id, message) => {
+                    match message["type"].as_str() {
+                        Some("article") => {
+                            multiplayer::load_article_message(game, &message);
+                            self.racing = true;
+                            self.reported = None;
+                            self.scoreboard.clear();
+                            self.winner = None;
+                            changed = true;
+                        }
+
+                        Some("scoreboard") => {
+                            self.scoreboard = message["rivals"]
+                                .as_array()
+                                .map(|rivals| rivals.iter().filter_map(Rival::from_json).collect())
+                                .unwrap_or_default();
+                            self.winner = message["winner"].as_str().map(String::from);
+                            changed = true;
+                        }
+
+                        _ => { }
+                    }
+                }
+
+                Event::Disconnected(_) => {
+                    return Err(anyhow!("Lost connection to host at {}", self.address));
+                }
+
+                Event::Connected(..) => { }
+            }
+        }
+
+        if self.racing {
+            let rival = Rival::of_game(game);
+
+            if !self.reported.as_ref().is_some_and(|old| old.same_score(&rival)) {
+                let mut message = rival.to_json();
+                message["type"] = json!("progress");
+                message["title"] = json!(game.title());
+                self.connection.send(&message)?;
+                self.reported = Some(rival);
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::article_parser::SourceFormat;
+    use crate::net::tests::poll_until;
+
+    fn progress_message(title: &str, solved: bool) -> Value {
+        json!({
+            "type": "progress",
+            "title": title,
+            "player": "rival",
+            "guesses": 3,
+            "progress": 1.0,
+            "solved": solved,
+        })
+    }
+
+    #[test]
+    fn stale_progress_does_not_win_the_next_race() {
+        let mut host = Host::start(0).unwrap();
+        let mut game = Game::new();
+        game.load_source("en", "Old", SourceFormat::Wikitext, "An old article.");
+        host.poll(&mut game);
+
+        let (mut connection, _events) = net::connect(&format!("127.0.0.1:{}", host.listener.port)).unwrap();
+        assert!(poll_until(|| {
+            host.poll(&mut game);
+            !host.clients.is_empty()
+        }));
+
+        // The host moves on before the rival's last report arrives
+        game.load_source("en", "New", SourceFormat::Wikitext, "A new article.");
+        host.poll(&mut game);
+        connection.send(&progress_message("Old", true)).unwrap();
+        connection.send(&progress_message("New", false)).unwrap();
+
+        assert!(poll_until(|| {
+            host.poll(&mut game);
+            host.rivals.len() == 2
+        }));
+        assert_eq!(host.winner, None);
+
+        connection.send(&progress_message("New", true)).unwrap();
+        assert!(poll_until(|| {
+            host.poll(&mut game);
+            host.winner.is_some()
+        }));
+        assert_eq!(host.winner.as_deref(), Some("rival"));
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use crate::article_parser;
+use crate::article_parser::{PageKind, SourceFormat, Token};
+use crate::difficulty;
+use crate::difficulty::Difficulty;
+use crate::game::{Game, TokenTreatment};
+use crate::word_frequency::WordFrequency;
+
+// A bot that plays a game on its own. It only looks at the article the
+// way a human player sees it, so it never learns more about a blanked
+// word than its length.
+
+// How many of the most frequent words are guessed before looking at the article
+const OPENING_GUESSES: usize = 25;
+
+// How much more a blank in the title is worth than one in the body
+const TITLE_WEIGHT: f32 = 20.0;
+
+// Words starting like a revealed word are often other forms of it, like
+// "felines" next to "feline", and are this much more likely
+const RELATED_WEIGHT: f32 = 10.0;
+
+// How many letters words need to have in common to count as related
+const STEM_LENGTH: usize = 4;
+
+pub struct Solver {
+    frequency: WordFrequency,
+}
+
+impl Solver {
+    pub fn new(frequency: WordFrequency) -> Self {
+        Solver { frequency }
+    }
+
+    // All words of the article, weighted by where they occur
+    fn words(game: &Game) -> Vec<(&Token, f32)> {
+        let Some(wiki_article) = &game.wiki_article else { return Vec::new() };
+
+        let title = wiki_article.title.iter().map(|token| (token, TITLE_WEIGHT));
+        let content = wiki_article.content.iter().flat_map(|section| section.tokens()).map(|token| (token, 1.0));
+
+        title
+            .chain(content)
+            .filter(|(token, _weight)| matches!(token, Token::Word(..)))
+            .collect()
+    }
+
+    fn stem(word: &str) -> Option<String> {
+        let stem: String = word.chars().take(STEM_LENGTH).collect::<String>().to_lowercase();
+        (stem.chars().count() == STEM_LENGTH).then_some(stem)
+    }
+
+    // Lengths of all words still blanked out, weighted by where they
+    // occur, and the stems of the words shown
+    fn context(game: &Game) -> (BTreeMap<usize, f32>, BTreeSet<String>) {
+        let mut lengths = BTreeMap::new();
+        let mut stems = BTreeSet::new();
+
+        for (token, weight) in Self::words(game) {
+            match game.get_token_treatment(token) {
+                TokenTreatment::Blank => *lengths.entry(token.char_count()).or_insert(0.0) += weight,
+                _ => stems.extend(Self::stem(token.get_str())),
+            }
+        }
+
+        (lengths, stems)
+    }
+
+    pub fn next_guess(&self, game: &Game) -> Option<String> {
+        let unguessed = self.frequency.words
+            .iter()
+            .enumerate()
+            .filter(|(_rank, word)| !game.guesses.contains(*word));
+
+        if game.guesses.len() < OPENING_GUESSES {
+            return unguessed.map(|(_rank, word)| word.clone()).next();
+        }
+
+        // After the opening, only words that fit one of the remaining
+        // blanks can still be right. Prefer words that fit many blanks,
+        // especially in the title, are frequent, and are related to the
+        // words already shown.
+        let (lengths, stems) = Self::context(game);
+
+        unguessed
+            .filter_map(|(rank, word)| {
+                let blanks = lengths.get(&word.chars().count())?;
+                let related = Self::stem(word).is_some_and(|stem| stems.contains(&stem));
+                let weight = if related { RELATED_WEIGHT } else { 1.0 };
+
+                Some((blanks * weight / (rank as f32 + 10.0), word))
+            })
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_score, word)| word.clone())
+    }
+
+    // Plays until the title is complete, returning the number of guesses
+    // needed, or None if the bot ran out of words or guesses.
+    pub fn solve(&self, game: &mut Game, max_guesses: usize) -> Option<usize> {
+        while !game.title_complete() {
+            if game.guesses.len() >= max_guesses {
+                return None;
+            }
+
+            let guess = self.next_guess(game)?;
+            game.guess(&guess);
+        }
+
+        Some(game.guesses.len())
+    }
+}
+
+fn print_usage() {
+    println!("Usage: rsdctl solve <article directory> --words <word list> [--max-guesses <n>] [--language <code>] [--template-rules <file>]");
+    println!();
+    println!("The bot only guesses words from the word list, one word per line,");
+    println!("most frequent first. It needs a long list with names of people and");
+    println!("places, such as one counted from Wikipedia, to guess most titles.");
+    println!();
+    println!("Every file in the article directory is read as the wikitext of");
+    println!("the article named like the file, without extension. Files ending");
+    println!("in .html are read as Parsoid HTML, as saved from the REST API.");
+}
+
+// Entry point of the `solve` subcommand
+pub fn run(args: &[String]) -> Result<()> {
+    let mut directory = None;
+    let mut frequency = None;
+    let mut max_guesses = 5000;
+    let mut language = String::from("en");
+    let mut template_rules = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--words" => {
+                let path = args.next().ok_or(anyhow!("--words needs a file name"))?;
+                frequency = Some(WordFrequency::load(Path::new(path))?);
+            }
+
+            "--max-guesses" => {
+                let n = args.next().ok_or(anyhow!("--max-guesses needs a number"))?;
+                max_guesses = n.parse()?;
+            }
+
+            "--template-rules" => {
+                template_rules = Some(args.next().ok_or(anyhow!("--template-rules needs a file name"))?.clone());
+            }
+
+            "--language" => {
+                language = args.next().ok_or(anyhow!("--language needs a language code"))?.clone();
+            }
+
+            _ if directory.is_none() => {
+                directory = Some(arg.clone());
+            }
+
+            _ => {
+                print_usage();
+                return Err(anyhow!("Unexpected argument {}", arg));
+            }
+        }
+    }
+
+    let Some(directory) = directory else {
+        print_usage();
+        return Err(anyhow!("No article directory given"));
+    };
+
+    // The built-in list of common words has none of the names most
+    // titles are made of, so the bot would hardly ever solve one
+    let Some(frequency) = frequency else {
+        print_usage();
+        return Err(anyhow!("No word list given, the built-in one is too short to guess titles"));
+    };
+
+    let solver = Solver::new(frequency);
+
+    let mut paths: Vec<_> = fs::read_dir(&directory)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut solved = 0;
+    let mut redirects = 0;
+    let mut total_guesses = 0;
+
+    for path in &paths {
+        let title = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or(anyhow!("Invalid file name {}", path.display()))?
+            .replace('_', " ");
+        let source = fs::read_to_string(path)?;
+
+        let format = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("html") => SourceFormat::Html,
+            _ => SourceFormat::Wikitext,
+        };
+
+        let mut game = Game::new();
+        if let Some(rules) = &template_rules {
+            game.parser_config.templates.load_rules_file(Path::new(rules))?;
+        }
+        game.load_source(&language, &title, format, &source);
+
+        // Saved redirects have no text, the article they lead to should be saved instead
+        if game.page_kind == PageKind::Redirect {
+            let target = article_parser::redirect_target(&source).unwrap_or_default();
+            println!("{:>6}  {:11}  {} (redirect to {})", "-", "redirect", title, target);
+            redirects += 1;
+            continue;
+        }
+
+        let estimate = game.wiki_article
+            .as_ref()
+            .map_or(1.0, |article| difficulty::estimate(article, &solver.frequency));
+        let estimate = format!("{:.2} {:6}", estimate, Difficulty::of_score(estimate).name());
+
+        match solver.solve(&mut game, max_guesses) {
+            Some(guesses) => {
+                println!("{:6}  {}  {}", guesses, estimate, title);
+                solved += 1;
+                total_guesses += guesses;
+            }
+
+            None => {
+                println!("{:>6}  {}  {}", "-", estimate, title);
+            }
+        }
+    }
+
+    println!();
+    println!("Solved {} of {} articles", solved, paths.len() - redirects);
+    if redirects > 0 {
+        println!("Skipped {} redirects", redirects);
+    }
+    if solved > 0 {
+        println!("Average guesses needed: {:.1}", total_guesses as f32 / solved as f32);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guesses_title_words_from_the_list() {
+        let mut game = Game::new();
+        game.load_source("en", "Felis catus", SourceFormat::Wikitext, "The '''domestic cat''' (''Felis catus'') is a small mammal.");
+
+        let solver = Solver::new(WordFrequency::parse("the\nis\na\nsmall\ncat\nfelis\ncatus\n"));
+        assert_eq!(solver.solve(&mut game, 100), Some(7));
+
+        // Words missing from the list can never be guessed
+        game.load_source("en", "Felis catus", SourceFormat::Wikitext, "The cat is a small mammal.");
+        let solver = Solver::new(WordFrequency::parse("the\nis\na\nsmall\ncat\n"));
+        assert_eq!(solver.solve(&mut game, 100), None);
+    }
+
+    #[test]
+    fn prefers_words_related_to_the_ones_shown() {
+        let solver = Solver::new(WordFrequency::parse("a\nhunts\nfeline\nrodent\nanimals\nfelines\n"));
+
+        let next_guess = |body: &str| {
+            let mut game = Game::new();
+            game.load_source("en", "Felines", SourceFormat::Wikitext, body);
+
+            for word in ["a", "hunts", "feline", "rodent"] {
+                game.guess(word);
+            }
+            for i in game.guesses.len()..OPENING_GUESSES {
+                game.guess(&format!("opening{}", i));
+            }
+
+            solver.next_guess(&game)
+        };
+
+        assert_eq!(next_guess("A rodent hunts."), Some(String::from("animals")));
+        assert_eq!(next_guess("A feline hunts."), Some(String::from("felines")));
+    }
+}
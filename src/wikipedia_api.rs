@@ -3,8 +3,46 @@ use ::reqwest::redirect::Policy;
 use reqwest::blocking as reqwest;
 use serde_json::Value;
 
-pub fn download_article(language: &str, title: &str) -> Result<(String, String)> {
-    let query = format!("https://{}.wikipedia.org/w/api.php?action=parse&page={}&prop=wikitext&formatversion=2&format=json", language, title);
+use crate::article_parser::{self, SourceFormat};
+
+// Redirects to redirects are rare, and longer chains are probably loops
+const MAX_REDIRECTS: usize = 3;
+
+pub struct Page {
+    pub title: String,
+    pub source: String,
+    pub format: SourceFormat,
+
+    // From the page properties, which know about disambiguation
+    // templates we do not
+    pub disambiguation: bool,
+}
+
+// Follows redirects, so the returned title is that of the actual article
+pub fn download_article(language: &str, title: &str, format: SourceFormat) -> Result<Page> {
+    match format {
+        SourceFormat::Html => download_html(language, title),
+        SourceFormat::Markdown | SourceFormat::PlainText => Err(anyhow!("Wikipedia does not serve articles as {}", format.name())),
+        SourceFormat::Wikitext => download_wikitext(language, title),
+    }
+}
+
+fn download_wikitext(language: &str, title: &str) -> Result<Page> {
+    let mut page = download_page(language, title)?;
+
+    // The API resolves redirects itself, this is in case it hands one back
+    for _ in 0..MAX_REDIRECTS {
+        match article_parser::redirect_target(&page.source) {
+            Some(target) => page = download_page(language, &target)?,
+            None => return Ok(page),
+        }
+    }
+
+    Err(anyhow!("Too many redirects, last one at {}", page.title))
+}
+
+fn download_page(language: &str, title: &str) -> Result<Page> {
+    let query = format!("https://{}.wikipedia.org/w/api.php?action=parse&page={}&prop=wikitext|properties&redirects=1&formatversion=2&format=json", language, title);
 
     let content = reqwest::get(query)?;
     let content = content.text()?;
@@ -20,7 +58,37 @@ pub fn download_article(language: &str, title: &str) -> Result<(String, String)>
         .and_then(|val| val.as_str())
         .ok_or(anyhow!("JSON response did not contain page title"))?;
 
-    Ok((String::from(page_title), String::from(wikitext)))
+    let disambiguation = content
+        .pointer("/parse/properties/disambiguation")
+        .is_some();
+
+    Ok(Page {
+        title: String::from(page_title),
+        source: String::from(wikitext),
+        format: SourceFormat::Wikitext,
+        disambiguation,
+    })
+}
+
+// The REST API follows redirects on its own
+fn download_html(language: &str, title: &str) -> Result<Page> {
+    let path = title.replace(' ', "_").replace('/', "%2F");
+    let query = format!("https://{}.wikipedia.org/api/rest_v1/page/html/{}", language, path);
+
+    let content = reqwest::get(query)?.error_for_status()?;
+    let content = content.text()?;
+
+    let page_title = article_parser::html_title(&content)
+        .unwrap_or_else(|| title.replace('_', " "));
+
+    Ok(Page {
+        title: page_title,
+        source: content,
+        format: SourceFormat::Html,
+
+        // The HTML marks disambiguation pages itself
+        disambiguation: false,
+    })
 }
 
 pub fn random_english_article() -> Result<String> {
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+const BUILTIN_WORD_FREQUENCY: &str = include_str!("assets/word_frequency.txt");
+
+// A list of common words, most frequent first
+pub struct WordFrequency {
+    pub words: Vec<String>,
+    ranks: HashMap<String, usize>,
+}
+
+impl WordFrequency {
+    // One word per line, most frequent first. Anything after the first
+    // whitespace on a line (such as a count) is ignored.
+    pub fn parse(list: &str) -> WordFrequency {
+        let words: Vec<String> = list
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(|word| word.to_lowercase())
+            .collect();
+
+        let mut ranks = HashMap::new();
+        for (rank, word) in words.iter().enumerate() {
+            ranks.entry(word.clone()).or_insert(rank);
+        }
+
+        WordFrequency { words, ranks }
+    }
+
+    pub fn builtin() -> WordFrequency {
+        Self::parse(BUILTIN_WORD_FREQUENCY)
+    }
+
+    pub fn load(path: &Path) -> Result<WordFrequency> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    // Zero for the most frequent word, None for words not in the list
+    pub fn rank(&self, word: &str) -> Option<usize> {
+        self.ranks.get(&word.to_lowercase()).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+}